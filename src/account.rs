@@ -0,0 +1,76 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use storage::Timestamp;
+
+pub const MAX_NAME_LEN: usize = 32;
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+/// A registered player.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Account {
+    pub name: String,
+    /// PHC string of the argon2 hash
+    pub password_hash: String,
+    pub created_at: Timestamp,
+}
+
+/// Name and password sent to `/register` and `/login`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Credentials {
+    pub name: String,
+    pub password: String,
+}
+
+impl Credentials {
+    /// Checks a name and password for a new account.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() || self.name.len() > MAX_NAME_LEN {
+            return Err(format!("Name must be between 1 and {} bytes", MAX_NAME_LEN));
+        }
+        // names are sent in slash commands, which are split on spaces
+        if self.name.chars().any(char::is_whitespace) {
+            return Err("Name must not contain spaces".to_owned());
+        }
+        if self.password.chars().count() < MIN_PASSWORD_LEN {
+            return Err(format!(
+                "Password must be at least {} characters",
+                MIN_PASSWORD_LEN
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| e.to_string())
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+#[test]
+fn test_passwords() {
+    let hash = hash_password("correct horse").unwrap();
+    assert!(hash.starts_with("$argon2"));
+    assert!(verify_password("correct horse", &hash));
+    assert!(!verify_password("battery staple", &hash));
+    assert!(!verify_password("correct horse", "not a hash"));
+
+    let credentials = |name: &str, password: &str| Credentials {
+        name: name.to_owned(),
+        password: password.to_owned(),
+    };
+    assert!(credentials("pipopa", "correct horse").validate().is_ok());
+    assert!(credentials("pi popa", "correct horse").validate().is_err());
+    assert!(credentials("pipopa", "short").validate().is_err());
+}
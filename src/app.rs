@@ -1,21 +1,32 @@
 use std::time::{Duration, Instant};
 
+use crate::account::Credentials;
+use crate::config::Config;
 use crate::message;
+use crate::protocol::{ClientMessage, Request, RequestId};
 use crate::server;
+use crate::storage;
 use actix::*;
 use actix_web::server::HttpServer;
-use actix_web::{fs, http, ws, App as ActixApp, Error, HttpRequest, HttpResponse};
-use reversi::board::{Color, Move as ReversiMove};
-use std::str::FromStr;
+use actix_web::{
+    fs, http, ws, App as ActixApp, AsyncResponder, Error, HttpRequest, HttpResponse, Json, Path,
+    Query,
+};
+use futures::Future;
+use reversi::board::Move as ReversiMove;
 
 /// How often heartbeat pings are sent
-const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 /// How long before lack of client response causes a timeout
-const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+pub const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// SQLite database finished games are archived in
+pub const ARCHIVE_PATH: &str = "reversi.sqlite3";
 
 /// This is our websocket route state, this state is shared with all route instances via `HttpContext::state()`
 struct WsGameSessionState {
     addr: Addr<server::GameServer>,
+    heartbeat_interval: Duration,
+    client_timeout: Duration,
 }
 
 /// Entry point for our route
@@ -27,22 +38,179 @@ fn chat_route(req: &HttpRequest<WsGameSessionState>) -> Result<HttpResponse, Err
             hb: Instant::now(),
             room: "Main".to_owned(),
             name: None,
-            color: None,
         },
     )
 }
 
+/// The best rated players as JSON
+fn leaderboard_route(
+    req: &HttpRequest<WsGameSessionState>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    req.state()
+        .addr
+        .send(message::Leaderboard {
+            limit: server::LEADERBOARD_SIZE,
+        })
+        .from_err()
+        .map(|res| match res {
+            Ok(ratings) => HttpResponse::Ok().json(ratings),
+            Err(e) => error_response(e),
+        })
+        .responder()
+}
+
+/// An error as JSON, with a status code matching it
+fn error_response(e: server::ReversiError) -> HttpResponse {
+    use crate::server::ReversiError;
+
+    let mut response = match e {
+        ReversiError::NoSuchRoom(_) => HttpResponse::NotFound(),
+        ReversiError::NameTaken(_) => HttpResponse::Conflict(),
+        ReversiError::InvalidCredentials | ReversiError::InvalidSessionToken => {
+            HttpResponse::Unauthorized()
+        }
+        ReversiError::Storage => HttpResponse::InternalServerError(),
+        ReversiError::Busy => HttpResponse::ServiceUnavailable(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response.json(server::ReversiMessage::error(None, e))
+}
+
+/// Replies to `/register` and `/login` with a session token
+fn token_response(res: Result<String, server::ReversiError>) -> HttpResponse {
+    match res {
+        Ok(token) => HttpResponse::Ok().json(serde_json::json!({ "token": token })),
+        Err(e) => error_response(e),
+    }
+}
+
+fn register_route(
+    (req, credentials): (HttpRequest<WsGameSessionState>, Json<Credentials>),
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    req.state()
+        .addr
+        .send(message::Register {
+            credentials: credentials.into_inner(),
+        })
+        .from_err()
+        .map(token_response)
+        .responder()
+}
+
+fn login_route(
+    (req, credentials): (HttpRequest<WsGameSessionState>, Json<Credentials>),
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    req.state()
+        .addr
+        .send(message::Login {
+            credentials: credentials.into_inner(),
+        })
+        .from_err()
+        .map(token_response)
+        .responder()
+}
+
+fn logout_route(
+    (req, logout): (HttpRequest<WsGameSessionState>, Json<message::Logout>),
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    req.state()
+        .addr
+        .send(logout.into_inner())
+        .from_err()
+        .map(|res| match res {
+            Ok(()) => HttpResponse::NoContent().finish(),
+            Err(e) => error_response(e),
+        })
+        .responder()
+}
+
+/// Public rooms as JSON
+fn rooms_route(
+    req: &HttpRequest<WsGameSessionState>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    req.state()
+        .addr
+        .send(message::RoomSummaries)
+        .from_err()
+        .map(|rooms| HttpResponse::Ok().json(rooms))
+        .responder()
+}
+
+fn room_route(
+    (req, name): (HttpRequest<WsGameSessionState>, Path<String>),
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    req.state()
+        .addr
+        .send(message::RoomDetails {
+            name: name.into_inner(),
+        })
+        .from_err()
+        .map(|res| match res {
+            Ok(room) => HttpResponse::Ok().json(room),
+            Err(e) => error_response(e),
+        })
+        .responder()
+}
+
+fn game_route(
+    (req, id): (HttpRequest<WsGameSessionState>, Path<storage::GameId>),
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    req.state()
+        .addr
+        .send(message::GetGame {
+            id: id.into_inner(),
+        })
+        .from_err()
+        .map(|res| match res {
+            Ok(Some(game)) => HttpResponse::Ok().json(game),
+            Ok(None) => HttpResponse::NotFound().finish(),
+            Err(e) => {
+                error!("Failed to read a game: {}", e);
+                HttpResponse::InternalServerError().finish()
+            }
+        })
+        .responder()
+}
+
+/// Archived games, see `message::FindGames`
+fn games_route(
+    (req, query): (HttpRequest<WsGameSessionState>, Query<message::FindGames>),
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    req.state()
+        .addr
+        .send(query.into_inner())
+        .from_err()
+        .map(|res| match res {
+            Ok(games) => HttpResponse::Ok().json(games),
+            Err(e) => error_response(e),
+        })
+        .responder()
+}
+
+/// Legal moves and evaluation of a position
+fn analyze_route(
+    (req, analyze): (HttpRequest<WsGameSessionState>, Json<message::Analyze>),
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    req.state()
+        .addr
+        .send(analyze.into_inner())
+        .from_err()
+        .map(|res| match res {
+            Ok(analysis) => HttpResponse::Ok().json(analysis),
+            Err(e) => error_response(e),
+        })
+        .responder()
+}
+
 struct WsGameSession {
     /// unique session id
     id: usize,
-    /// Client must send ping at least once per 10 seconds (CLIENT_TIMEOUT),
-    /// otherwise we drop connection.
+    /// Client must send ping at least once per `client_timeout`, otherwise we
+    /// drop connection.
     hb: Instant,
     /// joined room
     room: String,
-    /// color
-    color: Option<Color>,
-    /// peer name
+    /// account name once authenticated
     name: Option<String>,
 }
 
@@ -87,20 +255,15 @@ impl Actor for WsGameSession {
     }
 }
 
-/// Handle messages from chat server, we simply send it to peer websocket
-impl Handler<message::Message> for WsGameSession {
-    type Result = ();
-
-    fn handle(&mut self, msg: message::Message, ctx: &mut Self::Context) {
-        ctx.text(msg.0);
-    }
-}
-
 impl Handler<server::ReversiMessage> for WsGameSession {
     type Result = ();
 
     fn handle(&mut self, msg: server::ReversiMessage, ctx: &mut Self::Context) {
-        println!("{:?}", serde_json::to_string(&msg).unwrap());
+        // matchmaking decides the room for us
+        if let Some(room) = msg.matched_room() {
+            self.room = room.to_owned();
+        }
+        debug!("{:?}", serde_json::to_string(&msg).unwrap());
         ctx.text(serde_json::to_string(&msg).unwrap());
     }
 }
@@ -108,7 +271,7 @@ impl Handler<server::ReversiMessage> for WsGameSession {
 /// WebSocket message handler
 impl StreamHandler<ws::Message, ws::ProtocolError> for WsGameSession {
     fn handle(&mut self, msg: ws::Message, ctx: &mut Self::Context) {
-        println!("WEBSOCKET MESSAGE: {:?}", msg);
+        debug!("WEBSOCKET MESSAGE: {:?}", msg);
         match msg {
             ws::Message::Ping(msg) => {
                 self.hb = Instant::now();
@@ -119,109 +282,15 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for WsGameSession {
             }
             ws::Message::Text(text) => {
                 let m = text.trim();
-                // we check for /sss type of messages
-                if m.starts_with('/') {
-                    let v: Vec<&str> = m.splitn(4, ' ').collect();
-                    match v[0] {
-                        "/listRooms" => {
-                            // Send ListRooms message to chat server and wait for
-                            // response
-                            println!("List rooms");
-                            ctx.state()
-                                .addr
-                                .send(message::ListRooms { uid: self.id })
-                                .into_actor(self)
-                                .then(|res, _, ctx| {
-                                    match res {
-                                        Ok(rooms) => {
-                                            ctx.text(serde_json::to_string(&rooms).unwrap());
-                                        }
-                                        _ => println!("Something is wrong"),
-                                    }
-                                    fut::ok(())
-                                })
-                                .wait(ctx)
-                            // .wait(ctx) pauses all events in context,
-                            // so actor wont receive any new messages until it get list
-                            // of rooms back
-                        }
-                        "/join" => {
-                            if v.len() == 3 {
-                                self.room = v[1].to_owned();
-                                let uname = v[2].to_owned();
-                                ctx.state().addr.do_send(message::Join {
-                                    name: self.room.clone(),
-                                    uid: self.id,
-                                    uname: uname,
-                                });
-                                ctx.text("joined");
-                            } else {
-                                ctx.text("!!! room name is required");
-                            }
-                        }
-                        "/makeRoom" => {
-                            println!("someone made room");
-                            if v.len() == 3 {
-                                self.room = v[1].to_owned();
-                                let uname = v[2].to_owned();
-                                ctx.state().addr.do_send(server::MakeRoom {
-                                    name: self.room.clone(),
-                                    uid: self.id,
-                                    uname: uname,
-                                    color: None,
-                                });
-                            } else if v.len() == 4 {
-                                self.room = v[1].to_owned();
-                                let uname = v[2].to_owned();
-                                let color = <Color as FromStr>::from_str(v[3]).unwrap();
-                                ctx.state().addr.do_send(server::MakeRoom {
-                                    name: self.room.clone(),
-                                    uid: self.id,
-                                    uname: uname,
-                                    color: Some(color),
-                                });
-                            } else {
-                                ctx.text("!!! room name is required");
-                            }
-                        }
-                        "/move" => {
-                            if v.len() == 4 {
-                                let color = if v[1].to_uppercase() == "BLACK" {
-                                    Color::Black
-                                } else {
-                                    Color::White
-                                };
-                                let m = ReversiMove {
-                                    x: v[2].parse().unwrap(),
-                                    y: v[3].parse().unwrap(),
-                                    color,
-                                };
-                                ctx.state().addr.do_send(server::ClientReversiMoveMessage {
-                                    id: self.id,
-                                    reversi_move: m,
-                                    room: self.room.clone(),
-                                })
-                            } else {
-                                ctx.text("!!! color is required");
-                            }
-                        }
-                        _ => ctx.text(format!("!!! unknown command: {:?}", m)),
+                // slash commands and JSON requests
+                if !m.is_empty() {
+                    match Request::parse(m) {
+                        Ok(request) => self.handle_request(request, ctx),
+                        Err((id, e)) => self.reply_error(id, !m.starts_with('{'), e, ctx),
                     }
-                } else {
-                    //let msg = if let Some(ref name) = self.name {
-                    //    format!("{}: {}", name, m)
-                    //} else {
-                    //    m.to_owned()
-                    //};
-                    //// send message to chat server
-                    //ctx.state().addr.do_send(server::ClientReversiMoveMessage {
-                    //    id: self.id,
-                    //    msg: msg,
-                    //    room: self.room.clone(),
-                    //})
                 }
             }
-            ws::Message::Binary(bin) => println!("Unexpected binary"),
+            ws::Message::Binary(bin) => warn!("Unexpected binary"),
             ws::Message::Close(_) => {
                 ctx.stop();
             }
@@ -230,15 +299,321 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for WsGameSession {
 }
 
 impl WsGameSession {
+    fn handle_request(&mut self, request: Request, ctx: &mut <Self as Actor>::Context) {
+        let Request {
+            id,
+            message: command,
+            legacy,
+        } = request;
+        match command {
+            ClientMessage::ListRooms => {
+                // Send ListRooms message to chat server and wait for
+                // response
+                debug!("List rooms");
+                ctx.state()
+                    .addr
+                    .send(message::ListRooms { uid: self.id })
+                    .into_actor(self)
+                    .then(move |res, _, ctx| {
+                        match res {
+                            // legacy clients get the bare list
+                            Ok(rooms) if legacy => {
+                                ctx.text(serde_json::to_string(&rooms).unwrap());
+                            }
+                            Ok(rooms) => ctx.text(
+                                serde_json::to_string(&server::ReversiMessage::room_list(
+                                    id, rooms,
+                                ))
+                                .unwrap(),
+                            ),
+                            _ => warn!("Something is wrong"),
+                        }
+                        fut::ok(())
+                    })
+                    .wait(ctx)
+                // .wait(ctx) pauses all events in context,
+                // so actor wont receive any new messages until it get list
+                // of rooms back
+            }
+            ClientMessage::SubscribeLobby => {
+                let subscribe = message::SubscribeLobby { uid: self.id };
+                self.send_command(subscribe, id, legacy, None, ctx);
+            }
+            ClientMessage::UnsubscribeLobby => {
+                let unsubscribe = message::UnsubscribeLobby { uid: self.id };
+                self.send_command(unsubscribe, id, legacy, None, ctx);
+            }
+            ClientMessage::Leaderboard => ctx
+                .state()
+                .addr
+                .send(message::Leaderboard {
+                    limit: server::LEADERBOARD_SIZE,
+                })
+                .into_actor(self)
+                .then(move |res, act, ctx| {
+                    match res {
+                        Ok(Ok(ratings)) => ctx.text(
+                            serde_json::to_string(&server::ReversiMessage::leaderboard(
+                                id, ratings,
+                            ))
+                            .unwrap(),
+                        ),
+                        Ok(Err(e)) => act.reply_error(id, legacy, e, ctx),
+                        _ => warn!("Something is wrong"),
+                    }
+                    fut::ok(())
+                })
+                .wait(ctx),
+            ClientMessage::Join {
+                room,
+                name,
+                password,
+                code,
+            } => {
+                self.room = room;
+                let join = message::Join {
+                    name: self.room.clone(),
+                    uid: self.id,
+                    uname: name,
+                    password,
+                    code,
+                };
+                self.send_command(join, id, legacy, Some("joined"), ctx);
+            }
+            ClientMessage::Watch {
+                room,
+                password,
+                code,
+            } => {
+                self.room = room;
+                let watch = message::Watch {
+                    name: self.room.clone(),
+                    uid: self.id,
+                    password,
+                    code,
+                };
+                self.send_command(watch, id, legacy, None, ctx);
+            }
+            ClientMessage::Chat { text, lobby } => {
+                let channel = if lobby {
+                    server::ChatChannel::Lobby
+                } else {
+                    server::ChatChannel::Room(self.room.clone())
+                };
+                let chat = server::Chat {
+                    uid: self.id,
+                    channel,
+                    text,
+                };
+                self.send_command(chat, id, legacy, None, ctx);
+            }
+            ClientMessage::Invite => ctx
+                .state()
+                .addr
+                .send(server::Invite {
+                    uid: self.id,
+                    room: self.room.clone(),
+                })
+                .into_actor(self)
+                .then(move |res, act, ctx| {
+                    match res {
+                        Ok(Ok(code)) => ctx.text(
+                            serde_json::to_string(&server::ReversiMessage::invite(
+                                id,
+                                act.room.clone(),
+                                code,
+                            ))
+                            .unwrap(),
+                        ),
+                        Ok(Err(e)) => act.reply_error(id, legacy, e, ctx),
+                        _ => warn!("Something is wrong"),
+                    }
+                    fut::ok(())
+                })
+                .wait(ctx),
+            ClientMessage::Authenticate { token } => ctx
+                .state()
+                .addr
+                .send(message::Authenticate {
+                    token,
+                    uid: self.id,
+                })
+                .into_actor(self)
+                .then(move |res, act, ctx| {
+                    match res {
+                        Ok(Ok(name)) => {
+                            act.name = Some(name.clone());
+                            ctx.text(
+                                serde_json::to_string(&server::ReversiMessage::authenticated(
+                                    id, name,
+                                ))
+                                .unwrap(),
+                            );
+                        }
+                        Ok(Err(e)) => act.reply_error(id, legacy, e, ctx),
+                        _ => warn!("Something is wrong"),
+                    }
+                    fut::ok(())
+                })
+                .wait(ctx),
+            ClientMessage::Resume { token } => ctx
+                .state()
+                .addr
+                .send(message::Resume {
+                    token,
+                    uid: self.id,
+                })
+                .into_actor(self)
+                .then(move |res, act, ctx| {
+                    match res {
+                        Ok(Ok(room)) => act.room = room,
+                        Ok(Err(e)) => act.reply_error(id, legacy, e, ctx),
+                        _ => warn!("Something is wrong"),
+                    }
+                    fut::ok(())
+                })
+                .wait(ctx),
+            ClientMessage::Queue {
+                name,
+                time_control,
+                band,
+            } => {
+                let queue = server::Queue {
+                    uid: self.id,
+                    uname: name,
+                    time_control,
+                    band,
+                };
+                self.send_command(queue, id, legacy, None, ctx);
+            }
+            ClientMessage::Unqueue => {
+                let unqueue = server::Unqueue { uid: self.id };
+                self.send_command(unqueue, id, legacy, None, ctx);
+            }
+            ClientMessage::MakeRoom {
+                room,
+                name,
+                color,
+                opponent,
+                time_control,
+                private,
+                password,
+            } => {
+                debug!("someone made room");
+                self.room = room;
+                let make_room = server::MakeRoom {
+                    name: self.room.clone(),
+                    uid: self.id,
+                    uname: name,
+                    color,
+                    opponent,
+                    time_control,
+                    private,
+                    password,
+                };
+                self.send_command(make_room, id, legacy, None, ctx);
+            }
+            ClientMessage::Move { color, x, y } => {
+                let reversi_move = server::ClientReversiMoveMessage {
+                    id: self.id,
+                    reversi_move: ReversiMove { x, y, color },
+                    room: self.room.clone(),
+                };
+                self.send_command(reversi_move, id, legacy, None, ctx);
+            }
+            ClientMessage::Resign => self.send_action(server::Action::Resign, id, legacy, ctx),
+            ClientMessage::OfferDraw => {
+                self.send_action(server::Action::OfferDraw, id, legacy, ctx)
+            }
+            ClientMessage::AcceptDraw => {
+                self.send_action(server::Action::AcceptDraw, id, legacy, ctx)
+            }
+            ClientMessage::Abort => self.send_action(server::Action::Abort, id, legacy, ctx),
+            ClientMessage::Takeback => {
+                self.send_action(server::Action::RequestTakeback, id, legacy, ctx)
+            }
+            ClientMessage::AcceptTakeback => {
+                self.send_action(server::Action::AcceptTakeback, id, legacy, ctx)
+            }
+            ClientMessage::DeclineTakeback => {
+                self.send_action(server::Action::DeclineTakeback, id, legacy, ctx)
+            }
+            ClientMessage::Rematch => self.send_action(server::Action::Rematch, id, legacy, ctx),
+        }
+    }
+
+    fn send_action(
+        &mut self,
+        action: server::Action,
+        id: Option<RequestId>,
+        legacy: bool,
+        ctx: &mut <Self as Actor>::Context,
+    ) {
+        let action = server::PlayerAction {
+            id: self.id,
+            room: self.room.clone(),
+            action,
+        };
+        self.send_command(action, id, legacy, None, ctx);
+    }
+
+    /// Sends a command to the game server and reports a refusal to the
+    /// client. Legacy clients are sent `ok` on success.
+    fn send_command<M>(
+        &mut self,
+        msg: M,
+        id: Option<RequestId>,
+        legacy: bool,
+        ok: Option<&'static str>,
+        ctx: &mut <Self as Actor>::Context,
+    ) where
+        M: actix::Message<Result = Result<(), server::ReversiError>> + Send + 'static,
+        server::GameServer: Handler<M>,
+    {
+        ctx.state()
+            .addr
+            .send(msg)
+            .into_actor(self)
+            .then(move |res, act, ctx| {
+                match res {
+                    Ok(Ok(())) => {
+                        if let (true, Some(ok)) = (legacy, ok) {
+                            ctx.text(ok);
+                        }
+                    }
+                    Ok(Err(e)) => act.reply_error(id, legacy, e, ctx),
+                    _ => warn!("Something is wrong"),
+                }
+                fut::ok(())
+            })
+            .wait(ctx)
+    }
+
+    /// Legacy clients get errors as `!!! ` prefixed text
+    fn reply_error(
+        &self,
+        id: Option<RequestId>,
+        legacy: bool,
+        error: server::ReversiError,
+        ctx: &mut <Self as Actor>::Context,
+    ) {
+        if legacy {
+            ctx.text(format!("!!! {}", error));
+        } else {
+            ctx.text(serde_json::to_string(&server::ReversiMessage::error(id, error)).unwrap());
+        }
+    }
+
     /// helper method that sends ping to client every second.
     ///
     /// also this method checks heartbeats from client
     fn hb(&self, ctx: &mut ws::WebsocketContext<Self, WsGameSessionState>) {
-        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+        let interval = ctx.state().heartbeat_interval;
+        ctx.run_interval(interval, |act, ctx| {
             // check client heartbeats
-            if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
+            if Instant::now().duration_since(act.hb) > ctx.state().client_timeout {
                 // heartbeat timed out
-                println!("Websocket Client heartbeat failed, disconnecting!");
+                warn!("Websocket Client heartbeat failed, disconnecting!");
 
                 // notify chat server
                 ctx.state().addr.do_send(message::Disconnect { id: act.id });
@@ -258,18 +633,38 @@ impl WsGameSession {
 pub struct App;
 
 impl App {
-    pub fn start() {
-        let _ = env_logger::init();
+    /// Runs the server until it is stopped. Fails if it cannot start.
+    pub fn start(config: Config) -> Result<(), String> {
+        env_logger::Builder::new()
+            .parse_filters(&config.log_level)
+            .init();
+        for key in &config.unknown_env {
+            warn!("Ignoring {}, which is not a setting", key);
+        }
         let sys = actix::System::new("websocket-reversi-example");
 
+        let archive = storage::SqliteArchive::open(&config.archive)
+            .map_err(|e| format!("Cannot open {}: {}", config.archive.display(), e))?;
+
         // Start chat server actor in separate thread
-        let server = Arbiter::start(|_| server::GameServer::default());
+        let resume_grace = config.resume_grace();
+        let max_rooms = config.max_rooms;
+        let server = Arbiter::start(move |_| {
+            server::GameServer::new(Box::new(archive))
+                .with_resume_grace(resume_grace)
+                .with_max_rooms(max_rooms)
+        });
 
         // Create Http server with websocket support
-        HttpServer::new(move || {
+        let (heartbeat_interval, client_timeout) =
+            (config.heartbeat_interval(), config.client_timeout());
+        let static_root = config.static_root.clone();
+        let mut http_server = HttpServer::new(move || {
             // Websocket sessions state
             let state = WsGameSessionState {
                 addr: server.clone(),
+                heartbeat_interval,
+                client_timeout,
             };
 
             ActixApp::with_state(state)
@@ -281,48 +676,144 @@ impl App {
                             .finish()
                     })
                 })
+                .resource("/leaderboard", |r| {
+                    r.method(http::Method::GET).a(leaderboard_route)
+                })
+                .resource("/register", |r| {
+                    r.method(http::Method::POST).with_async(register_route)
+                })
+                .resource("/login", |r| {
+                    r.method(http::Method::POST).with_async(login_route)
+                })
+                .resource("/logout", |r| {
+                    r.method(http::Method::POST).with_async(logout_route)
+                })
+                // REST API
+                .resource("/api/rooms", |r| r.method(http::Method::GET).a(rooms_route))
+                .resource("/api/rooms/{name}", |r| {
+                    r.method(http::Method::GET).with_async(room_route)
+                })
+                .resource("/api/games", |r| {
+                    r.method(http::Method::GET).with_async(games_route)
+                })
+                .resource("/api/games/{id}", |r| {
+                    r.method(http::Method::GET).with_async(game_route)
+                })
+                .resource("/api/analyze", |r| {
+                    r.method(http::Method::POST).with_async(analyze_route)
+                })
                 // websocket
                 .resource("/ws/", |r| r.route().f(chat_route))
                 // static resources
-                .handler("/", fs::StaticFiles::new("static/").unwrap())
-        })
-        .bind("127.0.0.1:8080")
-        .unwrap()
-        .start();
+                .handler("/", fs::StaticFiles::new(&static_root).unwrap())
+        });
+        for addr in &config.bind {
+            http_server = http_server
+                .bind(addr)
+                .map_err(|e| format!("Cannot bind {}: {}", addr, e))?;
+        }
+        http_server.start();
 
-        println!("Started http server: 127.0.0.1:8080");
+        info!("Started http server: {}", config.bind.join(", "));
         let _ = sys.run();
+        Ok(())
     }
 }
 
-#[test]
-fn test_make_room() {
-    use crate::server;
-    use actix_web::*;
-    use futures::{Future, Stream};
-    use std::{thread, time};
+#[cfg(test)]
+macro_rules! read_ws_assert {
+    ($server:expr, $reader:ident, $expect:expr) => {
+        let (item, $reader) = $server.execute($reader.into_future()).unwrap();
+        assert_eq!(item, Some($expect));
+    };
+}
 
-    macro_rules! read_ws_assert {
-        ($server:expr, $reader:ident, $expect:expr) => {
-            let (item, $reader) = $server.execute($reader.into_future()).unwrap();
-            assert_eq!(item, Some($expect));
-        };
+/// Reads from `reader` until a `ReversiMessage` of `kind` arrives,
+/// skipping everything else up to the end of the game.
+#[cfg(test)]
+fn read_kind(
+    srv: &mut actix_web::test::TestServer,
+    mut reader: ws::ClientReader,
+    kind: &str,
+) -> (serde_json::Value, ws::ClientReader) {
+    use futures::Stream;
+
+    loop {
+        let (item, rest) = srv.execute(reader.into_future()).unwrap();
+        reader = rest;
+        match item {
+            Some(ws::Message::Text(text)) => {
+                if let Ok(message) = serde_json::from_str::<serde_json::Value>(&text) {
+                    if message["kind"] == kind {
+                        return (message, reader);
+                    }
+                    // nothing else comes after the end of the game
+                    assert_ne!(message["kind"], "GameOver", "no {} message", kind);
+                }
+            }
+            Some(ws::Message::Ping(_)) | Some(ws::Message::Pong(_)) => {}
+            other => panic!("unexpected message: {:?}", other),
+        }
     }
+}
+
+/// Starts a `GameServer` on its own system and a test http server
+/// whose every request is upgraded to a `WsGameSession`.
+#[cfg(test)]
+fn start_test_server() -> actix_web::test::TestServer {
+    start_test_server_with(|server| server)
+}
+
+/// Like `start_test_server`, with the `GameServer` adjusted by `configure`.
+#[cfg(test)]
+fn start_test_server_with(
+    configure: fn(server::GameServer) -> server::GameServer,
+) -> actix_web::test::TestServer {
+    use actix_web::*;
+    use std::thread;
 
     let (tx, rx) = std::sync::mpsc::channel();
 
     thread::spawn(move || {
         let sys = actix::System::new("http-server");
-        let addr = Arbiter::start(|_| server::GameServer::default());
+        let addr = Arbiter::start(move |_| {
+            configure(
+                crate::server::GameServer::default().with_resume_grace(Duration::from_secs(1)),
+            )
+        });
         let _ = tx.send(addr);
         let _ = sys.run();
     });
 
     let server = rx.recv().unwrap();
-    let mut srv = test::TestServer::build_with_state(move || WsGameSessionState {
+    test::TestServer::build_with_state(move || WsGameSessionState {
         addr: server.clone(),
+        heartbeat_interval: HEARTBEAT_INTERVAL,
+        client_timeout: CLIENT_TIMEOUT,
     })
     .start(|app| {
+        app.resource("/register", |r| {
+            r.method(http::Method::POST).with_async(register_route)
+        });
+        app.resource("/login", |r| {
+            r.method(http::Method::POST).with_async(login_route)
+        });
+        app.resource("/logout", |r| {
+            r.method(http::Method::POST).with_async(logout_route)
+        });
+        app.resource("/api/rooms", |r| r.method(http::Method::GET).a(rooms_route));
+        app.resource("/api/rooms/{name}", |r| {
+            r.method(http::Method::GET).with_async(room_route)
+        });
+        app.resource("/api/games", |r| {
+            r.method(http::Method::GET).with_async(games_route)
+        });
+        app.resource("/api/games/{id}", |r| {
+            r.method(http::Method::GET).with_async(game_route)
+        });
+        app.resource("/api/analyze", |r| {
+            r.method(http::Method::POST).with_async(analyze_route)
+        });
         app.handler(|req| {
             ws::start(
                 req,
@@ -331,11 +822,18 @@ fn test_make_room() {
                     hb: Instant::now(),
                     room: "Main".to_owned(),
                     name: None,
-                    color: None,
                 },
             )
         })
-    });
+    })
+}
+
+#[test]
+fn test_make_room() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server();
 
     let (r1, mut w1) = srv.ws().unwrap();
 
@@ -362,3 +860,991 @@ fn test_make_room() {
         )
     );
 }
+
+#[test]
+fn test_make_room_with_computer() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server();
+    let (r1, mut w1) = srv.ws().unwrap();
+
+    w1.text("/makeRoom Solo pipopa white cpu:0");
+    read_ws_assert!(
+        srv,
+        r1,
+        ws::Message::Text(
+            "{\"kind\":\"GameStart\",\"body\":{\"GameStart\":\"White\"}}".to_string()
+        )
+    );
+    let (item, r1) = srv.execute(r1.into_future()).unwrap();
+    match item {
+        Some(ws::Message::Text(text)) => assert!(text.starts_with("{\"kind\":\"ResumeToken\"")),
+        other => panic!("unexpected message: {:?}", other),
+    }
+
+    // the computer plays black and moves first
+    let (item, _) = srv.execute(r1.into_future()).unwrap();
+    match item {
+        Some(ws::Message::Text(text)) => {
+            assert!(text.starts_with("{\"kind\":\"Game\""));
+            assert!(text.contains("\"turn\":\"White\""));
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn test_leave_abandons_game() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server();
+    let (r1, mut w1) = srv.ws().unwrap();
+    let (r2, mut w2) = srv.ws().unwrap();
+
+    w1.text("/makeRoom Shiba pipopa black");
+    w1.ping("");
+    w2.text("/join Shiba Tatsuo");
+    read_ws_assert!(srv, r1, ws::Message::Pong("".to_string()));
+    read_ws_assert!(srv, r2, ws::Message::Text("joined".to_string()));
+    read_ws_assert!(
+        srv,
+        r1,
+        ws::Message::Text(
+            "{\"kind\":\"GameStart\",\"body\":{\"GameStart\":\"Black\"}}".to_string()
+        )
+    );
+    let (item, r1) = srv.execute(r1.into_future()).unwrap();
+    match item {
+        Some(ws::Message::Text(text)) => assert!(text.starts_with("{\"kind\":\"ResumeToken\"")),
+        other => panic!("unexpected message: {:?}", other),
+    }
+
+    // white does not come back within the grace period
+    drop((r2, w2));
+    let (item, _) = srv.execute(r1.into_future()).unwrap();
+    match item {
+        Some(ws::Message::Text(text)) => {
+            assert!(text.starts_with("{\"kind\":\"GameOver\""));
+            assert!(text.ends_with(",\"Black\",\"Abandoned\"]}}"));
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn test_watch() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server();
+    let (r1, mut w1) = srv.ws().unwrap();
+    let (r2, mut w2) = srv.ws().unwrap();
+    let (r3, mut w3) = srv.ws().unwrap();
+
+    w3.text("/watch Nowhere");
+    read_ws_assert!(
+        srv,
+        r3,
+        ws::Message::Text("!!! Room Nowhere does not exist".to_string())
+    );
+
+    w1.text("/makeRoom Shiba pipopa black");
+    w1.ping("");
+    w2.text("/join Shiba Tatsuo");
+    read_ws_assert!(srv, r1, ws::Message::Pong("".to_string()));
+    read_ws_assert!(srv, r2, ws::Message::Text("joined".to_string()));
+    read_ws_assert!(
+        srv,
+        r1,
+        ws::Message::Text(
+            "{\"kind\":\"GameStart\",\"body\":{\"GameStart\":\"Black\"}}".to_string()
+        )
+    );
+
+    // the spectator gets the current position straight away
+    w3.text("/watch Shiba");
+    let (item, r3) = srv.execute(r3.into_future()).unwrap();
+    match item {
+        Some(ws::Message::Text(text)) => {
+            assert!(text.starts_with("{\"kind\":\"Game\""));
+            assert!(text.contains("\"turn\":\"Black\""));
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+
+    // but cannot move, even for the side to move
+    w3.text("/move black f5");
+    read_ws_assert!(
+        srv,
+        r3,
+        ws::Message::Text("!!! Spectators cannot move".to_string())
+    );
+    w1.text("/move black d3");
+    let (_, _r1) = read_kind(&mut srv, r1, "Game");
+    let (message, _) = read_kind(&mut srv, r3, "Game");
+    let board = &message["body"]["Game"]["board"];
+    assert_eq!(board[2 * 8 + 3], "black");
+    assert_eq!(board[4 * 8 + 5], "empty");
+}
+
+#[test]
+fn test_resume() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server();
+    let (r1, mut w1) = srv.ws().unwrap();
+    let (r2, mut w2) = srv.ws().unwrap();
+
+    w1.text("/makeRoom Shiba pipopa black");
+    w1.ping("");
+    w2.text("/join Shiba Tatsuo");
+    read_ws_assert!(srv, r1, ws::Message::Pong("".to_string()));
+    read_ws_assert!(srv, r2, ws::Message::Text("joined".to_string()));
+    read_ws_assert!(
+        srv,
+        r1,
+        ws::Message::Text(
+            "{\"kind\":\"GameStart\",\"body\":{\"GameStart\":\"Black\"}}".to_string()
+        )
+    );
+    let (item, r1) = srv.execute(r1.into_future()).unwrap();
+    let token = match item {
+        Some(ws::Message::Text(text)) => {
+            let message: serde_json::Value = serde_json::from_str(&text).unwrap();
+            message["body"]["ResumeToken"].as_str().unwrap().to_owned()
+        }
+        other => panic!("unexpected message: {:?}", other),
+    };
+
+    // black drops and comes back on a new connection
+    drop((r1, w1));
+    let (r3, mut w3) = srv.ws().unwrap();
+    w3.text("/resume nonsense");
+    read_ws_assert!(
+        srv,
+        r3,
+        ws::Message::Text("!!! Invalid resume token".to_string())
+    );
+    w3.text(format!("/resume {}", token));
+    read_ws_assert!(
+        srv,
+        r3,
+        ws::Message::Text(
+            "{\"kind\":\"GameStart\",\"body\":{\"GameStart\":\"Black\"}}".to_string()
+        )
+    );
+    // a resume hands out a fresh token
+    let (message, r3) = read_kind(&mut srv, r3, "ResumeToken");
+    assert_ne!(message["body"]["ResumeToken"], token.as_str());
+    let (item, r3) = srv.execute(r3.into_future()).unwrap();
+    match item {
+        Some(ws::Message::Text(text)) => {
+            assert!(text.starts_with("{\"kind\":\"Game\""));
+            assert!(text.contains("\"turn\":\"Black\""));
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+
+    // the old token has been used up
+    let (r4, mut w4) = srv.ws().unwrap();
+    w4.text(format!("/resume {}", token));
+    read_ws_assert!(
+        srv,
+        r4,
+        ws::Message::Text("!!! Invalid resume token".to_string())
+    );
+
+    // the seat outlives the grace period and can be played from
+    ::std::thread::sleep(Duration::from_millis(1500));
+    w3.text("/move black d3");
+    let (item, _) = srv.execute(r3.into_future()).unwrap();
+    match item {
+        Some(ws::Message::Text(text)) => {
+            assert!(text.starts_with("{\"kind\":\"Game\""));
+            assert!(text.contains("\"turn\":\"White\""));
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn test_room_removed_after_game_ends_while_held() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server_with(|server| server.with_resume_grace(Duration::from_secs(3)));
+    let (r1, mut w1) = srv.ws().unwrap();
+    let (r2, mut w2) = srv.ws().unwrap();
+
+    w1.text("/makeRoom Shiba pipopa black fischer:1+0");
+    w1.ping("");
+    w2.text("/join Shiba Tatsuo");
+    read_ws_assert!(srv, r1, ws::Message::Pong("".to_string()));
+    read_ws_assert!(srv, r2, ws::Message::Text("joined".to_string()));
+
+    // both drop, and the flag falls while their seats are held
+    drop((r1, w1));
+    drop((r2, w2));
+    ::std::thread::sleep(Duration::from_millis(1500));
+    let (_, rooms) = get_json(&mut srv, "/api/rooms");
+    assert_eq!(rooms.as_array().unwrap().len(), 1);
+
+    // the empty room goes once the seats expire
+    ::std::thread::sleep(Duration::from_millis(2500));
+    let (_, rooms) = get_json(&mut srv, "/api/rooms");
+    assert_eq!(rooms, serde_json::json!([]));
+}
+
+#[test]
+fn test_json_requests() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server();
+    let (r1, mut w1) = srv.ws().unwrap();
+
+    w1.text("{\"type\":\"MakeRoom\",\"id\":1,\"room\":\"Shiba\",\"name\":\"pipopa\"}");
+    w1.text("{\"type\":\"ListRooms\",\"id\":2}");
+    let (item, r1) = srv.execute(r1.into_future()).unwrap();
+    match item {
+        Some(ws::Message::Text(text)) => {
+            let message: serde_json::Value = serde_json::from_str(&text).unwrap();
+            assert_eq!(message["id"], 2);
+            assert_eq!(message["kind"], "RoomList");
+            assert_eq!(message["body"]["RoomList"][0][0], "Shiba");
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+
+    w1.text("{\"type\":\"Join\",\"id\":3}");
+    let (item, r1) = srv.execute(r1.into_future()).unwrap();
+    match item {
+        Some(ws::Message::Text(text)) => {
+            let message: serde_json::Value = serde_json::from_str(&text).unwrap();
+            assert_eq!(message["id"], 3);
+            assert_eq!(message["kind"], "ReversiError");
+            assert!(message["body"]["ReversiError"]["InvalidRequest"].is_string());
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+
+    // malformed legacy commands are answered instead of panicking
+    w1.text("/move black x 4");
+    read_ws_assert!(
+        srv,
+        r1,
+        ws::Message::Text("!!! invalid square: x 4".to_string())
+    );
+}
+
+#[test]
+fn test_error_replies() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server();
+    let (r1, mut w1) = srv.ws().unwrap();
+    let (r2, mut w2) = srv.ws().unwrap();
+
+    w1.text("/makeRoom Shiba pipopa black");
+    w1.text("/move black d3");
+    read_ws_assert!(
+        srv,
+        r1,
+        ws::Message::Text("!!! Game is not started".to_string())
+    );
+    w2.text("{\"type\":\"MakeRoom\",\"id\":4,\"room\":\"Shiba\",\"name\":\"Tatsuo\"}");
+    read_ws_assert!(
+        srv,
+        r2,
+        ws::Message::Text(
+            "{\"id\":4,\"kind\":\"ReversiError\",\"body\":{\"ReversiError\":{\"RoomExists\":\"Shiba\"}}}"
+                .to_string()
+        )
+    );
+    w2.text("/join Nowhere Tatsuo");
+    read_ws_assert!(
+        srv,
+        r2,
+        ws::Message::Text("!!! Room Nowhere does not exist".to_string())
+    );
+    w2.text("/join Shiba Tatsuo");
+    read_ws_assert!(srv, r2, ws::Message::Text("joined".to_string()));
+    read_ws_assert!(
+        srv,
+        r1,
+        ws::Message::Text(
+            "{\"kind\":\"GameStart\",\"body\":{\"GameStart\":\"Black\"}}".to_string()
+        )
+    );
+
+    // white moving first, then black on an illegal square
+    w2.text("{\"type\":\"Move\",\"id\":5,\"color\":\"White\",\"x\":2,\"y\":4}");
+    let (message, _) = read_kind(&mut srv, r2, "ReversiError");
+    assert_eq!(message["id"], 5);
+    assert_eq!(message["body"]["ReversiError"], "NotYourTurn");
+    let (_, r1) = read_kind(&mut srv, r1, "ResumeToken");
+    w1.text("/move black a1");
+    read_ws_assert!(
+        srv,
+        r1,
+        ws::Message::Text("!!! a1 is not available".to_string())
+    );
+}
+
+#[test]
+fn test_flag_fall() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server();
+    let (r1, mut w1) = srv.ws().unwrap();
+    let (r2, mut w2) = srv.ws().unwrap();
+
+    w1.text("/makeRoom Shiba pipopa black fischer:1+0");
+    w1.ping("");
+    w2.text("/join Shiba Tatsuo");
+    read_ws_assert!(srv, r1, ws::Message::Pong("".to_string()));
+    read_ws_assert!(srv, r2, ws::Message::Text("joined".to_string()));
+
+    // white's clock does not run while black thinks
+    w1.text("/move black d3");
+    let (_, _r1) = read_kind(&mut srv, r1, "Game");
+    let (message, r2) = read_kind(&mut srv, r2, "Game");
+    let clock = &message["body"]["Game"]["clock"];
+    assert!(clock["white"].as_u64().unwrap() > 900);
+    assert_eq!(clock["running"], "White");
+
+    // and then runs out
+    let (message, _) = read_kind(&mut srv, r2, "GameOver");
+    let game_over = &message["body"]["GameOver"];
+    assert_eq!(game_over[0]["clock"]["white"], 0);
+    assert_eq!(game_over[1], "Black");
+    assert_eq!(game_over[2], "Timeout");
+}
+
+#[test]
+fn test_resign_draw_and_abort() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server();
+    let (r1, mut w1) = srv.ws().unwrap();
+    let (r2, mut w2) = srv.ws().unwrap();
+
+    w1.text("/makeRoom Shiba pipopa black");
+    w1.ping("");
+    w2.text("/join Shiba Tatsuo");
+    read_ws_assert!(srv, r1, ws::Message::Pong("".to_string()));
+    read_ws_assert!(srv, r2, ws::Message::Text("joined".to_string()));
+
+    w1.text("/move black d3");
+    let (_, r1) = read_kind(&mut srv, r1, "Game");
+    w1.text("/abort");
+    read_ws_assert!(
+        srv,
+        r1,
+        ws::Message::Text("!!! Game can only be aborted before the first move".to_string())
+    );
+    w1.text("/acceptDraw");
+    read_ws_assert!(
+        srv,
+        r1,
+        ws::Message::Text("!!! Your opponent has not offered a draw".to_string())
+    );
+
+    w2.text("/offerDraw");
+    let (message, _r2) = read_kind(&mut srv, r2, "DrawOffer");
+    assert_eq!(message["body"]["DrawOffer"], "White");
+    let (_, r1) = read_kind(&mut srv, r1, "DrawOffer");
+    w1.text("/acceptDraw");
+    let (message, _) = read_kind(&mut srv, r1, "GameOver");
+    assert_eq!(message["body"]["GameOver"][1], serde_json::Value::Null);
+    assert_eq!(message["body"]["GameOver"][2], "DrawAgreed");
+
+    let (r3, mut w3) = srv.ws().unwrap();
+    let (r4, mut w4) = srv.ws().unwrap();
+    w3.text("/makeRoom Akita pipopa black");
+    w3.ping("");
+    w4.text("/join Akita Tatsuo");
+    read_ws_assert!(srv, r3, ws::Message::Pong("".to_string()));
+    read_ws_assert!(srv, r4, ws::Message::Text("joined".to_string()));
+    w4.text("/resign");
+    let (message, _) = read_kind(&mut srv, r4, "GameOver");
+    assert_eq!(message["body"]["GameOver"][1], "Black");
+    assert_eq!(message["body"]["GameOver"][2], "Resigned");
+}
+
+#[test]
+fn test_takeback() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server();
+    let (r1, mut w1) = srv.ws().unwrap();
+    let (r2, mut w2) = srv.ws().unwrap();
+
+    w1.text("/makeRoom Shiba pipopa black");
+    w1.ping("");
+    w2.text("/join Shiba Tatsuo");
+    read_ws_assert!(srv, r1, ws::Message::Pong("".to_string()));
+    read_ws_assert!(srv, r2, ws::Message::Text("joined".to_string()));
+
+    let (_, r2) = read_kind(&mut srv, r2, "ResumeToken");
+    w2.text("/takeback");
+    read_ws_assert!(
+        srv,
+        r2,
+        ws::Message::Text("!!! You have no move to take back".to_string())
+    );
+    w1.text("/move black d3");
+    let (_, r1) = read_kind(&mut srv, r1, "Game");
+    w2.text("/move white c5");
+    let (_, r2) = read_kind(&mut srv, r2, "Game");
+
+    // black asks twice, white declines then accepts
+    w1.text("/takeback");
+    let (message, r1) = read_kind(&mut srv, r1, "TakebackRequest");
+    assert_eq!(message["body"]["TakebackRequest"], "Black");
+    let (_, r2) = read_kind(&mut srv, r2, "TakebackRequest");
+    w2.text("/declineTakeback");
+    let (message, r2) = read_kind(&mut srv, r2, "TakebackDeclined");
+    assert_eq!(message["body"]["TakebackDeclined"], "White");
+    w2.text("/acceptTakeback");
+    read_ws_assert!(
+        srv,
+        r2,
+        ws::Message::Text("!!! Your opponent has not asked for a takeback".to_string())
+    );
+    w1.text("/takeback");
+    let (_, _r1) = read_kind(&mut srv, r1, "TakebackRequest");
+    let (_, r2) = read_kind(&mut srv, r2, "TakebackRequest");
+    w2.text("/acceptTakeback");
+    let (message, _) = read_kind(&mut srv, r2, "Game");
+    let game = &message["body"]["Game"];
+    assert_eq!(game["turn"], "Black");
+    assert_eq!(game["board"][19], "available");
+    assert_eq!(game["board"][34], "empty");
+}
+
+#[test]
+fn test_rematch() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server();
+    let (r1, mut w1) = srv.ws().unwrap();
+    let (r2, mut w2) = srv.ws().unwrap();
+
+    w1.text("/makeRoom Shiba pipopa black");
+    w1.ping("");
+    w2.text("/join Shiba Tatsuo");
+    read_ws_assert!(srv, r1, ws::Message::Pong("".to_string()));
+    read_ws_assert!(srv, r2, ws::Message::Text("joined".to_string()));
+
+    let (_, r1) = read_kind(&mut srv, r1, "ResumeToken");
+    w1.text("/rematch");
+    read_ws_assert!(
+        srv,
+        r1,
+        ws::Message::Text("!!! Game is still in progress".to_string())
+    );
+    w1.text("/resign");
+    let (_, r1) = read_kind(&mut srv, r1, "GameOver");
+    let (_, r2) = read_kind(&mut srv, r2, "GameOver");
+
+    w2.text("/rematch");
+    let (message, r2) = read_kind(&mut srv, r2, "RematchOffer");
+    assert_eq!(message["body"]["RematchOffer"], "White");
+    let (_, r1) = read_kind(&mut srv, r1, "RematchOffer");
+    w1.text("/rematch");
+    let (message, r1) = read_kind(&mut srv, r1, "GameStart");
+    assert_eq!(message["body"]["GameStart"], "White");
+    let (message, _r2) = read_kind(&mut srv, r2, "GameStart");
+    assert_eq!(message["body"]["GameStart"], "Black");
+
+    w1.text("{\"type\":\"ListRooms\",\"id\":1}");
+    let (message, _) = read_kind(&mut srv, r1, "RoomList");
+    let room = &message["body"]["RoomList"][0][1];
+    assert_eq!(room["player1"]["color"], "White");
+    assert_eq!(
+        room["score"],
+        serde_json::json!({"player1": 0, "player2": 1, "draws": 0})
+    );
+}
+
+#[test]
+fn test_queue() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server();
+    let (r1, mut w1) = srv.ws().unwrap();
+    let (r2, mut w2) = srv.ws().unwrap();
+
+    w1.text("/queue pipopa fischer:60+0");
+    let (message, r1) = read_kind(&mut srv, r1, "QueueStatus");
+    assert_eq!(message["body"]["QueueStatus"]["waiting"], 1);
+    // no one else wants an untimed game
+    w2.text("/queue Tatsuo");
+    let (message, r2) = read_kind(&mut srv, r2, "QueueStatus");
+    assert_eq!(message["body"]["QueueStatus"]["waiting"], 1);
+    w2.text("/unqueue");
+    let (message, r2) = read_kind(&mut srv, r2, "QueueStatus");
+    assert_eq!(message["body"]["QueueStatus"]["queued"], false);
+    w2.text("/unqueue");
+    read_ws_assert!(
+        srv,
+        r2,
+        ws::Message::Text("!!! You are not in the queue".to_string())
+    );
+
+    w2.text("/queue Tatsuo fischer:60+0");
+    let (message, r2) = read_kind(&mut srv, r2, "Matched");
+    let room = message["body"]["Matched"].as_str().unwrap().to_owned();
+    assert!(room.starts_with("match-"));
+    let (message, r2) = read_kind(&mut srv, r2, "GameStart");
+    let (matched, r1) = read_kind(&mut srv, r1, "Matched");
+    assert_eq!(matched["body"]["Matched"], room);
+
+    // the matched rooms are used for moves from then on
+    let (r, mut w) = if message["body"]["GameStart"] == "Black" {
+        (r2, w2)
+    } else {
+        (r1, w1)
+    };
+    w.text("/move black d3");
+    let (message, _) = read_kind(&mut srv, r, "Game");
+    assert_eq!(message["body"]["Game"]["turn"], "White");
+}
+
+#[test]
+fn test_queue_when_full() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server_with(|server| server.with_max_rooms(1));
+    let (r1, mut w1) = srv.ws().unwrap();
+    let (r2, mut w2) = srv.ws().unwrap();
+    let (r3, mut w3) = srv.ws().unwrap();
+
+    w3.text("/makeRoom Akita hikotq");
+    w3.ping("");
+    read_ws_assert!(srv, r3, ws::Message::Pong("".to_string()));
+
+    // the players are kept waiting while there is no room left
+    w1.text("/queue pipopa");
+    let (_, r1) = read_kind(&mut srv, r1, "QueueStatus");
+    w2.text("/queue Tatsuo");
+    let (message, r2) = read_kind(&mut srv, r2, "QueueStatus");
+    assert_eq!(message["body"]["QueueStatus"]["waiting"], 2);
+
+    // and matched once a room is free
+    drop((r3, w3));
+    let (message, _) = read_kind(&mut srv, r1, "Matched");
+    let room = message["body"]["Matched"].as_str().unwrap().to_owned();
+    let (message, _) = read_kind(&mut srv, r2, "Matched");
+    assert_eq!(message["body"]["Matched"], room);
+}
+
+/// Posts credentials to `path` and returns the status and JSON reply.
+#[cfg(test)]
+fn post_credentials(
+    srv: &mut actix_web::test::TestServer,
+    path: &str,
+    name: &str,
+    password: &str,
+) -> (http::StatusCode, serde_json::Value) {
+    use actix_web::HttpMessage;
+
+    let request = srv
+        .post()
+        .uri(srv.url(path))
+        .json(Credentials {
+            name: name.to_owned(),
+            password: password.to_owned(),
+        })
+        .unwrap();
+    let response = srv.execute(request.send()).unwrap();
+    let body = srv.execute(response.body()).unwrap();
+    (response.status(), serde_json::from_slice(&body).unwrap())
+}
+
+#[test]
+fn test_session_tokens() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv =
+        start_test_server_with(|server| server.with_session_lifetime(Duration::from_secs(1)));
+    let (_, registered) = post_credentials(&mut srv, "/register", "pipopa", "correct horse");
+    let (status, login) = post_credentials(&mut srv, "/login", "pipopa", "correct horse");
+    assert_eq!(status, http::StatusCode::OK);
+    let logout = |srv: &mut test::TestServer, token: &serde_json::Value| {
+        let request = srv
+            .post()
+            .uri(srv.url("/logout"))
+            .json(serde_json::json!({ "token": token }))
+            .unwrap();
+        srv.execute(request.send()).unwrap().status()
+    };
+
+    // a logged out token is refused
+    assert_eq!(
+        logout(&mut srv, &registered["token"]),
+        http::StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        logout(&mut srv, &registered["token"]),
+        http::StatusCode::UNAUTHORIZED
+    );
+    let (r1, mut w1) = srv.ws().unwrap();
+    w1.text(format!(
+        "/authenticate {}",
+        registered["token"].as_str().unwrap()
+    ));
+    read_ws_assert!(
+        srv,
+        r1,
+        ws::Message::Text("!!! Invalid session token".to_string())
+    );
+
+    // and so is one past its lifetime
+    ::std::thread::sleep(Duration::from_millis(1500));
+    w1.text(format!(
+        "/authenticate {}",
+        login["token"].as_str().unwrap()
+    ));
+    read_ws_assert!(
+        srv,
+        r1,
+        ws::Message::Text("!!! Invalid session token".to_string())
+    );
+}
+
+#[test]
+fn test_accounts_and_ratings() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server();
+    let (status, pipopa) = post_credentials(&mut srv, "/register", "pipopa", "correct horse");
+    assert_eq!(status, http::StatusCode::OK);
+    let (status, _) = post_credentials(&mut srv, "/register", "pipopa", "battery staple");
+    assert_eq!(status, http::StatusCode::CONFLICT);
+    post_credentials(&mut srv, "/register", "Tatsuo", "battery staple");
+    let (status, _) = post_credentials(&mut srv, "/login", "Tatsuo", "correct horse");
+    assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+    let (status, tatsuo) = post_credentials(&mut srv, "/login", "Tatsuo", "battery staple");
+    assert_eq!(status, http::StatusCode::OK);
+
+    let (r1, mut w1) = srv.ws().unwrap();
+    let (r2, mut w2) = srv.ws().unwrap();
+    // registered names are not free for guests
+    w1.text("/makeRoom Shiba pipopa black");
+    read_ws_assert!(
+        srv,
+        r1,
+        ws::Message::Text("!!! Name pipopa is already registered".to_string())
+    );
+    w1.text(format!(
+        "/authenticate {}",
+        pipopa["token"].as_str().unwrap()
+    ));
+    let (message, r1) = read_kind(&mut srv, r1, "Authenticated");
+    assert_eq!(message["body"]["Authenticated"], "pipopa");
+    w2.text(format!(
+        "{{\"type\":\"Authenticate\",\"token\":{}}}",
+        tatsuo["token"]
+    ));
+    let (_, r2) = read_kind(&mut srv, r2, "Authenticated");
+
+    // the account names are used whatever name is sent
+    w1.text("/makeRoom Shiba someone black");
+    w1.ping("");
+    w2.text("/join Shiba someone");
+    read_ws_assert!(srv, r1, ws::Message::Pong("".to_string()));
+    read_ws_assert!(srv, r2, ws::Message::Text("joined".to_string()));
+    w1.text("/resign");
+    let (_, r1) = read_kind(&mut srv, r1, "GameOver");
+
+    w1.text("{\"type\":\"Leaderboard\",\"id\":1}");
+    let (message, r1) = read_kind(&mut srv, r1, "Leaderboard");
+    assert_eq!(
+        message["body"]["Leaderboard"],
+        serde_json::json!([
+            {"name": "Tatsuo", "rating": 1516.0, "games": 1},
+            {"name": "pipopa", "rating": 1484.0, "games": 1},
+        ])
+    );
+
+    w1.text("{\"type\":\"ListRooms\",\"id\":2}");
+    let (message, _) = read_kind(&mut srv, r1, "RoomList");
+    let room = &message["body"]["RoomList"][0][1];
+    assert_eq!(room["player1"]["name"], "pipopa");
+    assert_eq!(room["player1"]["rating"], 1484);
+    assert_eq!(room["player2"]["rating"], 1516);
+}
+
+#[test]
+fn test_private_rooms() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server();
+    let (r1, mut w1) = srv.ws().unwrap();
+    let (r2, mut w2) = srv.ws().unwrap();
+    let (r3, mut w3) = srv.ws().unwrap();
+
+    w1.text("/makeRoom Shiba pipopa black private");
+    w1.text("/makeRoom Akita hikotq password:secret");
+    w1.text("{\"type\":\"ListRooms\",\"id\":1}");
+    let (message, r1) = read_kind(&mut srv, r1, "RoomList");
+    let rooms = message["body"]["RoomList"].as_array().unwrap();
+    assert_eq!(rooms.len(), 1);
+    assert_eq!(rooms[0][0], "Akita");
+    assert_eq!(rooms[0][1]["locked"], true);
+
+    w2.text("/join Akita Tatsuo password:guess");
+    read_ws_assert!(
+        srv,
+        r2,
+        ws::Message::Text("!!! Room Akita needs a password or an invite code".to_string())
+    );
+    w2.text("/join Shiba Tatsuo");
+    read_ws_assert!(
+        srv,
+        r2,
+        ws::Message::Text("!!! Room Shiba needs a password or an invite code".to_string())
+    );
+
+    // the second /makeRoom moved pipopa's session to Akita
+    w1.text("/invite");
+    let (message, r1) = read_kind(&mut srv, r1, "Invite");
+    assert_eq!(message["body"]["Invite"]["room"], "Akita");
+    let code = message["body"]["Invite"]["code"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+    assert_eq!(
+        message["body"]["Invite"]["url"],
+        format!("/html/reversi.html?room=Akita&code={}", code)
+    );
+    w2.text(format!("/join Akita Tatsuo code:{}", code));
+    read_ws_assert!(srv, r2, ws::Message::Text("joined".to_string()));
+    let (_, _r1) = read_kind(&mut srv, r1, "GameStart");
+
+    // codes work once
+    w3.text(format!("/watch Akita code:{}", code));
+    read_ws_assert!(
+        srv,
+        r3,
+        ws::Message::Text("!!! Room Akita needs a password or an invite code".to_string())
+    );
+    w3.text("/watch Akita password:secret");
+    let (message, _) = read_kind(&mut srv, r3, "Game");
+    assert_eq!(message["body"]["Game"]["turn"], "Black");
+}
+
+#[test]
+fn test_lobby_events() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server();
+    let (r1, mut w1) = srv.ws().unwrap();
+    let (r2, mut w2) = srv.ws().unwrap();
+    let (r3, mut w3) = srv.ws().unwrap();
+
+    w2.text("/makeRoom Shiba pipopa black");
+    w2.ping("");
+    read_ws_assert!(srv, r2, ws::Message::Pong("".to_string()));
+    w1.text("/subscribeLobby");
+    let (message, r1) = read_kind(&mut srv, r1, "RoomCreated");
+    assert_eq!(
+        message["body"]["RoomCreated"],
+        serde_json::json!({
+            "name": "Shiba",
+            "player1": "pipopa",
+            "player2": null,
+            "status": "Waiting",
+            "moves": 0,
+            "locked": false,
+        })
+    );
+
+    // private rooms stay out of the lobby
+    w3.text("/makeRoom Hidden Tatsuo private");
+    w3.text("/join Shiba Tatsuo");
+    read_ws_assert!(srv, r3, ws::Message::Text("joined".to_string()));
+    let (message, r1) = read_kind(&mut srv, r1, "RoomUpdated");
+    let summary = &message["body"]["RoomUpdated"];
+    assert_eq!(summary["player2"], "Tatsuo");
+    assert_eq!(summary["status"], "Playing");
+
+    w2.text("/move black d3");
+    let (_, r2) = read_kind(&mut srv, r2, "Game");
+    let (message, r1) = read_kind(&mut srv, r1, "RoomUpdated");
+    assert_eq!(message["body"]["RoomUpdated"]["moves"], 1);
+    w2.text("/resign");
+    let (_, r2) = read_kind(&mut srv, r2, "GameOver");
+    let (message, r1) = read_kind(&mut srv, r1, "RoomUpdated");
+    assert_eq!(message["body"]["RoomUpdated"]["status"], "Finished");
+
+    drop((r2, w2, r3, w3));
+    let (message, _) = read_kind(&mut srv, r1, "RoomClosed");
+    assert_eq!(message["body"]["RoomClosed"], "Shiba");
+}
+
+#[test]
+fn test_chat() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server();
+    let (r1, mut w1) = srv.ws().unwrap();
+    let (r2, mut w2) = srv.ws().unwrap();
+    let (r3, mut w3) = srv.ws().unwrap();
+
+    w1.text("/makeRoom Shiba pipopa black");
+    // plain text is not chat
+    w1.text("hello");
+    read_ws_assert!(
+        srv,
+        r1,
+        ws::Message::Text("!!! unknown command: \"hello\"".to_string())
+    );
+    w1.text("/chat hello");
+    let (message, r1) = read_kind(&mut srv, r1, "Chat");
+    let chat = &message["body"]["Chat"];
+    assert_eq!(chat["room"], "Shiba");
+    assert_eq!(chat["from"], "pipopa");
+    assert_eq!(chat["text"], "hello");
+    assert!(chat["sent_at"].as_u64().unwrap() > 0);
+
+    // scrollback for those coming in later
+    w2.text("/watch Shiba");
+    let (message, r2) = read_kind(&mut srv, r2, "Chat");
+    assert_eq!(message["body"]["Chat"]["text"], "hello");
+    w2.text("/chat nice room");
+    let (message, _r2) = read_kind(&mut srv, r2, "Chat");
+    assert_eq!(message["body"]["Chat"]["from"], "Guest");
+    let (message, r1) = read_kind(&mut srv, r1, "Chat");
+    assert_eq!(message["body"]["Chat"]["text"], "nice room");
+    w1.text(format!(
+        "/chat {}",
+        "a".repeat(crate::server::MAX_CHAT_LEN + 1)
+    ));
+    read_ws_assert!(
+        srv,
+        r1,
+        ws::Message::Text("!!! Chat messages are limited to 500 characters".to_string())
+    );
+
+    // the lobby is a channel of its own
+    w3.text("/subscribeLobby");
+    w3.text("{\"type\":\"Chat\",\"text\":\"anyone?\",\"lobby\":true}");
+    let (message, _) = read_kind(&mut srv, r3, "Chat");
+    assert_eq!(message["body"]["Chat"]["room"], serde_json::Value::Null);
+    assert_eq!(message["body"]["Chat"]["text"], "anyone?");
+}
+
+#[cfg(test)]
+fn get_json(
+    srv: &mut actix_web::test::TestServer,
+    path: &str,
+) -> (http::StatusCode, serde_json::Value) {
+    use actix_web::HttpMessage;
+
+    let request = srv.get().uri(srv.url(path)).finish().unwrap();
+    let response = srv.execute(request.send()).unwrap();
+    let body = srv.execute(response.body()).unwrap();
+    (
+        response.status(),
+        serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null),
+    )
+}
+
+#[test]
+fn test_rest_api() {
+    use actix_web::*;
+    use futures::Stream;
+
+    let mut srv = start_test_server();
+    let (r1, mut w1) = srv.ws().unwrap();
+    let (r2, mut w2) = srv.ws().unwrap();
+
+    w1.text("/makeRoom Shiba pipopa black");
+    w1.ping("");
+    w2.text("/join Shiba Tatsuo");
+    read_ws_assert!(srv, r1, ws::Message::Pong("".to_string()));
+    read_ws_assert!(srv, r2, ws::Message::Text("joined".to_string()));
+    w1.text("/move black d3");
+    let (_, r1) = read_kind(&mut srv, r1, "Game");
+
+    let (status, rooms) = get_json(&mut srv, "/api/rooms");
+    assert_eq!(status, http::StatusCode::OK);
+    assert_eq!(rooms[0]["name"], "Shiba");
+    assert_eq!(rooms[0]["status"], "Playing");
+    assert_eq!(rooms[0]["moves"], 1);
+
+    let (status, room) = get_json(&mut srv, "/api/rooms/Shiba");
+    assert_eq!(status, http::StatusCode::OK);
+    assert_eq!(room["player1"]["name"], "pipopa");
+    assert_eq!(room["player2"]["color"], "White");
+    assert_eq!(room["game"]["turn"], "White");
+    assert_eq!(room["game"]["board"].as_array().unwrap().len(), 64);
+    let (status, _) = get_json(&mut srv, "/api/rooms/Akita");
+    assert_eq!(status, http::StatusCode::NOT_FOUND);
+
+    w1.text("/resign");
+    read_kind(&mut srv, r1, "GameOver");
+    let (status, game) = get_json(&mut srv, "/api/games/1");
+    assert_eq!(status, http::StatusCode::OK);
+    assert_eq!(game["moves"], "d3");
+    assert_eq!(game["termination"], "Resigned");
+    let (status, _) = get_json(&mut srv, "/api/games/2");
+    assert_eq!(status, http::StatusCode::NOT_FOUND);
+    let (status, games) = get_json(&mut srv, "/api/games?player=pipopa");
+    assert_eq!(status, http::StatusCode::OK);
+    assert_eq!(games[0]["id"], 1);
+    let (_, games) = get_json(&mut srv, "/api/games?player=pipopa&to=1");
+    assert_eq!(games, serde_json::json!([]));
+    let (_, games) = get_json(&mut srv, "/api/games?from=1");
+    assert_eq!(games.as_array().unwrap().len(), 1);
+    let (status, _) = get_json(&mut srv, "/api/games");
+    assert_eq!(status, http::StatusCode::BAD_REQUEST);
+
+    let analyze = |srv: &mut test::TestServer, body: serde_json::Value| {
+        let request = srv.post().uri(srv.url("/api/analyze")).json(body).unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        let body = srv.execute(response.body()).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        (response.status(), body)
+    };
+    let (status, analysis) = analyze(
+        &mut srv,
+        serde_json::json!({
+            "position": "---------------------------OX------XO--------------------------- X",
+            "level": 2,
+        }),
+    );
+    assert_eq!(status, http::StatusCode::OK);
+    assert_eq!(
+        analysis["legal_moves"],
+        serde_json::json!(["d3", "c4", "f5", "e6"])
+    );
+    assert_eq!(analysis["depth"], 2);
+    let best_move = analysis["best_move"].clone();
+    assert_eq!(analysis["pv"][0], best_move);
+    assert!(analysis["legal_moves"]
+        .as_array()
+        .unwrap()
+        .contains(&best_move));
+    let (status, error) = analyze(&mut srv, serde_json::json!({ "position": "XO" }));
+    assert_eq!(status, http::StatusCode::BAD_REQUEST);
+    assert_eq!(error["kind"], "ReversiError");
+}
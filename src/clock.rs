@@ -0,0 +1,255 @@
+use reversi::board::Color;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Longest base time, increment or byoyomi period, in seconds
+pub const MAX_TIME: u64 = 24 * 60 * 60;
+
+/// Time control chosen when a room is made. Times are in seconds.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimeControl {
+    /// `base` for the whole game, plus `increment` after every move
+    Fischer { base: u64, increment: u64 },
+    /// `base` for the whole game, then `period` for every move once it has
+    /// run out
+    Byoyomi { base: u64, period: u64 },
+}
+
+impl TimeControl {
+    pub fn validate(&self) -> Result<(), String> {
+        let (first, second) = match *self {
+            TimeControl::Fischer { base, increment } => (base, increment),
+            TimeControl::Byoyomi { base, period } => (base, period),
+        };
+        if first > MAX_TIME || second > MAX_TIME {
+            return Err(format!("times must be at most {} seconds", MAX_TIME));
+        }
+        match *self {
+            TimeControl::Fischer { base: 0, .. } => Err("base time must not be 0".to_string()),
+            TimeControl::Byoyomi { base: 0, period: 0 } => {
+                Err("byoyomi needs a base time or a period".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimeControl::Fischer { base, increment } => write!(f, "fischer:{}+{}", base, increment),
+            TimeControl::Byoyomi { base, period } => write!(f, "byoyomi:{}+{}", base, period),
+        }
+    }
+}
+
+/// `fischer:<base>+<increment>` or `byoyomi:<base>+<period>`, in seconds.
+impl FromStr for TimeControl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid time control: {}", s);
+        let (kind, times) = s.split_at(s.find(':').ok_or_else(invalid)?);
+        let mut times = times[1..].splitn(2, '+').map(|t| t.parse::<u64>());
+        let (first, second) = match (times.next(), times.next()) {
+            (Some(Ok(first)), Some(Ok(second))) => (first, second),
+            _ => return Err(invalid()),
+        };
+        let control = match kind {
+            "fischer" => TimeControl::Fischer {
+                base: first,
+                increment: second,
+            },
+            "byoyomi" => TimeControl::Byoyomi {
+                base: first,
+                period: second,
+            },
+            _ => return Err(invalid()),
+        };
+        control.validate()?;
+        Ok(control)
+    }
+}
+
+/// Time left before each flag falls, in milliseconds, as sent to clients.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ClockTimes {
+    pub black: u64,
+    pub white: u64,
+    /// Whose clock is running
+    pub running: Option<Color>,
+}
+
+/// A pair of clocks, one of which runs at a time.
+#[derive(Clone, Debug)]
+pub struct Clock {
+    control: TimeControl,
+    /// Main time left for black and white, not counting the running clock
+    remaining: [Duration; 2],
+    /// The running clock and when it was started
+    running: Option<(Color, Instant)>,
+}
+
+fn index(color: Color) -> usize {
+    match color {
+        Color::Black => 0,
+        Color::White => 1,
+    }
+}
+
+fn millis(d: Duration) -> u64 {
+    d.as_secs() * 1000 + u64::from(d.subsec_millis())
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Self {
+        let base = match control {
+            TimeControl::Fischer { base, .. } | TimeControl::Byoyomi { base, .. } => base,
+        };
+        Clock {
+            control,
+            remaining: [Duration::from_secs(base); 2],
+            running: None,
+        }
+    }
+
+    /// Starts `color`'s clock, stopping the other one without counting its
+    /// time.
+    pub fn start(&mut self, color: Color, now: Instant) {
+        self.running = Some((color, now));
+    }
+
+    /// How long `color` has before the flag falls.
+    pub fn time_left(&self, color: Color, now: Instant) -> Duration {
+        let elapsed = match self.running {
+            Some((running, since)) if running == color => now.duration_since(since),
+            _ => Duration::from_secs(0),
+        };
+        let limit = match self.control {
+            TimeControl::Fischer { .. } => self.remaining[index(color)],
+            TimeControl::Byoyomi { period, .. } => {
+                self.remaining[index(color)].saturating_add(Duration::from_secs(period))
+            }
+        };
+        limit.checked_sub(elapsed).unwrap_or_default()
+    }
+
+    pub fn is_flagged(&self, color: Color, now: Instant) -> bool {
+        self.time_left(color, now) == Duration::from_secs(0)
+    }
+
+    /// Stops the running clock after its side has moved, adding the
+    /// increment or resetting the byoyomi period. Returns `false` if the
+    /// flag had already fallen.
+    pub fn punch(&mut self, now: Instant) -> bool {
+        let (color, since) = match self.running.take() {
+            Some(running) => running,
+            None => return true,
+        };
+        let elapsed = now.duration_since(since);
+        let remaining = &mut self.remaining[index(color)];
+        match self.control {
+            TimeControl::Fischer { increment, .. } => match remaining.checked_sub(elapsed) {
+                Some(left) if left > Duration::from_secs(0) => {
+                    *remaining = left.saturating_add(Duration::from_secs(increment));
+                    true
+                }
+                _ => {
+                    *remaining = Duration::from_secs(0);
+                    false
+                }
+            },
+            TimeControl::Byoyomi { period, .. } => match remaining.checked_sub(elapsed) {
+                Some(left) => {
+                    *remaining = left;
+                    true
+                }
+                None => {
+                    let overtime = elapsed - *remaining;
+                    *remaining = Duration::from_secs(0);
+                    overtime < Duration::from_secs(period)
+                }
+            },
+        }
+    }
+
+    /// Stops the running clock, counting its time, at the end of the game.
+    pub fn stop(&mut self, now: Instant) {
+        if let Some((color, since)) = self.running.take() {
+            let elapsed = now.duration_since(since);
+            let remaining = &mut self.remaining[index(color)];
+            *remaining = remaining.checked_sub(elapsed).unwrap_or_default();
+        }
+    }
+
+    pub fn times(&self, now: Instant) -> ClockTimes {
+        ClockTimes {
+            black: millis(self.time_left(Color::Black, now)),
+            white: millis(self.time_left(Color::White, now)),
+            running: self.running.map(|(color, _)| color),
+        }
+    }
+}
+
+#[test]
+fn test_time_control() {
+    assert_eq!(
+        "fischer:300+5".parse(),
+        Ok(TimeControl::Fischer {
+            base: 300,
+            increment: 5,
+        })
+    );
+    assert_eq!(
+        "byoyomi:0+30".parse::<TimeControl>().unwrap().to_string(),
+        "byoyomi:0+30"
+    );
+    assert!("fischer:0+5".parse::<TimeControl>().is_err());
+    assert!("byoyomi:18446744073709551615+5"
+        .parse::<TimeControl>()
+        .is_err());
+    assert!("fischer:300+86401".parse::<TimeControl>().is_err());
+    assert!("fischer:300".parse::<TimeControl>().is_err());
+    assert!("blitz:300+5".parse::<TimeControl>().is_err());
+}
+
+#[test]
+fn test_clock() {
+    let secs = Duration::from_secs;
+    let t0 = Instant::now();
+
+    let mut clock = Clock::new(TimeControl::Fischer {
+        base: 10,
+        increment: 2,
+    });
+    clock.start(Color::Black, t0);
+    assert_eq!(clock.time_left(Color::Black, t0 + secs(4)), secs(6));
+    assert_eq!(clock.time_left(Color::White, t0 + secs(4)), secs(10));
+    assert!(clock.punch(t0 + secs(4)));
+    clock.start(Color::White, t0 + secs(4));
+    assert_eq!(clock.time_left(Color::Black, t0 + secs(9)), secs(8));
+    assert!(clock.is_flagged(Color::White, t0 + secs(14)));
+    assert!(!clock.punch(t0 + secs(14)));
+
+    // the period comes back after every move in time
+    let mut clock = Clock::new(TimeControl::Byoyomi { base: 5, period: 3 });
+    clock.start(Color::Black, t0);
+    assert!(clock.punch(t0 + secs(7)));
+    clock.start(Color::Black, t0 + secs(7));
+    assert_eq!(clock.time_left(Color::Black, t0 + secs(8)), secs(2));
+    assert!(clock.punch(t0 + secs(9)));
+    clock.start(Color::Black, t0 + secs(9));
+    assert!(!clock.punch(t0 + secs(13)));
+
+    clock.start(Color::White, t0);
+    clock.stop(t0 + secs(1));
+    assert_eq!(
+        clock.times(t0 + secs(100)),
+        ClockTimes {
+            black: 3000,
+            white: 7000,
+            running: None,
+        }
+    );
+}
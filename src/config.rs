@@ -0,0 +1,364 @@
+use app::{ARCHIVE_PATH, CLIENT_TIMEOUT, HEARTBEAT_INTERVAL};
+use server::{MAX_ROOMS, RESUME_GRACE};
+use std::fmt;
+use std::fs;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Read when neither `--config` nor `REVERSI_CONFIG` is given, if it exists
+const DEFAULT_PATH: &str = "reversi.toml";
+/// Prefix of the environment variables overriding the config file
+const ENV_PREFIX: &str = "REVERSI_";
+const LOG_LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+/// Names of the settings, as in the config file
+const SETTINGS: [&str; 8] = [
+    "bind",
+    "static_root",
+    "heartbeat_interval",
+    "client_timeout",
+    "resume_grace",
+    "max_rooms",
+    "archive",
+    "log_level",
+];
+
+pub const USAGE: &str = "\
+Usage: websocket-reversi-server [--config <file>] [--<setting> <value>]...
+
+Settings, also read from the config file and REVERSI_<SETTING> variables:
+    --bind <address>              may be repeated, 127.0.0.1:8080 by default
+    --static-root <dir>
+    --heartbeat-interval <secs>
+    --client-timeout <secs>
+    --resume-grace <secs>
+    --max-rooms <n>
+    --archive <file>              SQLite database for finished games
+    --log-level <level>           off, error, warn, info, debug or trace";
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ConfigError(pub String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Server settings. Later sources override earlier ones: the defaults, the
+/// TOML file, `REVERSI_*` environment variables, then command line flags.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Addresses to listen on
+    pub bind: Vec<String>,
+    /// Directory the web client is served from
+    pub static_root: PathBuf,
+    /// Seconds between websocket pings
+    pub heartbeat_interval: u64,
+    /// Seconds without a pong before a client is dropped
+    pub client_timeout: u64,
+    /// Seconds a disconnected player's seat is held
+    pub resume_grace: u64,
+    /// Rooms open at the same time
+    pub max_rooms: usize,
+    /// SQLite database finished games are archived in
+    pub archive: PathBuf,
+    pub log_level: String,
+    /// `REVERSI_*` variables that name no setting, to be warned about
+    #[serde(skip)]
+    pub unknown_env: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind: vec!["127.0.0.1:8080".to_owned()],
+            static_root: PathBuf::from("static/"),
+            heartbeat_interval: HEARTBEAT_INTERVAL.as_secs(),
+            client_timeout: CLIENT_TIMEOUT.as_secs(),
+            resume_grace: RESUME_GRACE.as_secs(),
+            max_rooms: MAX_ROOMS,
+            archive: PathBuf::from(ARCHIVE_PATH),
+            log_level: "info".to_owned(),
+            unknown_env: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Builds the config from command line arguments (without the program
+    /// name) and environment variables, and validates it.
+    pub fn load<A, E>(args: A, env: E) -> Result<Config, Vec<ConfigError>>
+    where
+        A: IntoIterator<Item = String>,
+        E: IntoIterator<Item = (String, String)>,
+    {
+        let Args {
+            config: config_path,
+            flags,
+        } = parse_args(args).map_err(|e| vec![e])?;
+        let overrides: Vec<(String, String)> = env
+            .into_iter()
+            .filter_map(|(key, value)| {
+                key.strip_prefix(ENV_PREFIX)
+                    .map(|key| (key.to_lowercase(), value))
+            })
+            .collect();
+
+        let path = config_path.or_else(|| {
+            overrides
+                .iter()
+                .find(|(key, _)| key == "config")
+                .map(|(_, value)| PathBuf::from(value))
+        });
+        let mut config = match path {
+            Some(path) => Config::read(&path).map_err(|e| vec![e])?,
+            None if Path::new(DEFAULT_PATH).exists() => {
+                Config::read(Path::new(DEFAULT_PATH)).map_err(|e| vec![e])?
+            }
+            None => Config::default(),
+        };
+
+        let mut errors = Vec::new();
+        for (key, value) in overrides.iter().filter(|(key, _)| key != "config") {
+            // other programs may use the prefix too
+            if !SETTINGS.contains(&key.as_str()) {
+                config
+                    .unknown_env
+                    .push(format!("{}{}", ENV_PREFIX, key.to_uppercase()));
+                continue;
+            }
+            if let Err(e) = config.set(key, value) {
+                errors.push(ConfigError(format!(
+                    "{}{}: {}",
+                    ENV_PREFIX,
+                    key.to_uppercase(),
+                    e
+                )));
+            }
+        }
+        for (key, value) in &flags {
+            if let Err(e) = config.set(key, value) {
+                errors.push(ConfigError(format!("--{}: {}", key.replace('_', "-"), e)));
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn read(path: &Path) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| ConfigError(format!("Cannot read {}: {}", path.display(), e)))?;
+        toml::from_str(&text).map_err(|e| ConfigError(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Overrides a setting by its name in the config file. `bind` takes a
+    /// comma separated list.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        fn number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+            value
+                .parse()
+                .map_err(|_| format!("{:?} is not a number", value))
+        }
+
+        match key {
+            "bind" => {
+                self.bind = value
+                    .split(',')
+                    .map(|addr| addr.trim().to_owned())
+                    .collect()
+            }
+            "static_root" => self.static_root = PathBuf::from(value),
+            "heartbeat_interval" => self.heartbeat_interval = number(value)?,
+            "client_timeout" => self.client_timeout = number(value)?,
+            "resume_grace" => self.resume_grace = number(value)?,
+            "max_rooms" => self.max_rooms = number(value)?,
+            "archive" => self.archive = PathBuf::from(value),
+            "log_level" => self.log_level = value.to_owned(),
+            _ => return Err("unknown setting".to_owned()),
+        }
+        Ok(())
+    }
+
+    /// Reports every invalid setting, not just the first.
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = Vec::new();
+        let mut error = |message: String| errors.push(ConfigError(message));
+
+        if self.bind.is_empty() {
+            error("bind: at least one address is required".to_owned());
+        }
+        for addr in &self.bind {
+            if addr.to_socket_addrs().is_err() {
+                error(format!("bind: {:?} is not a valid address", addr));
+            }
+        }
+        if !self.static_root.is_dir() {
+            error(format!(
+                "static_root: {} is not a directory",
+                self.static_root.display()
+            ));
+        }
+        if self.heartbeat_interval == 0 {
+            error("heartbeat_interval: must be at least 1 second".to_owned());
+        }
+        if self.client_timeout <= self.heartbeat_interval {
+            error("client_timeout: must be longer than heartbeat_interval".to_owned());
+        }
+        if self.max_rooms == 0 {
+            error("max_rooms: must be at least 1".to_owned());
+        }
+        let archive_dir = self.archive.parent().filter(|dir| dir != &Path::new(""));
+        if archive_dir.is_some_and(|dir| !dir.is_dir()) {
+            error(format!(
+                "archive: directory of {} does not exist",
+                self.archive.display()
+            ));
+        }
+        if !LOG_LEVELS.contains(&self.log_level.as_str()) {
+            error(format!(
+                "log_level: {:?} is not one of {}",
+                self.log_level,
+                LOG_LEVELS.join(", ")
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn heartbeat_interval(&self) -> Duration {
+        Duration::from_secs(self.heartbeat_interval)
+    }
+
+    pub fn client_timeout(&self) -> Duration {
+        Duration::from_secs(self.client_timeout)
+    }
+
+    pub fn resume_grace(&self) -> Duration {
+        Duration::from_secs(self.resume_grace)
+    }
+}
+
+/// The command line split into the config file path and `--setting value`
+/// pairs, with repeated `--bind` flags joined.
+struct Args {
+    config: Option<PathBuf>,
+    flags: Vec<(String, String)>,
+}
+
+fn parse_args<A>(args: A) -> Result<Args, ConfigError>
+where
+    A: IntoIterator<Item = String>,
+{
+    let mut config_path = None;
+    let mut flags: Vec<(String, String)> = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let flag = match arg.strip_prefix("--") {
+            Some(flag) => flag.to_owned(),
+            None => return Err(ConfigError(format!("Unexpected argument {:?}", arg))),
+        };
+        // --key=value or --key value
+        let (key, value) = match flag.find('=') {
+            Some(i) => (flag[..i].to_owned(), flag[i + 1..].to_owned()),
+            None => match args.next() {
+                Some(value) => (flag, value),
+                None => return Err(ConfigError(format!("--{} needs a value", flag))),
+            },
+        };
+        let key = key.replace('-', "_");
+        if key == "config" {
+            config_path = Some(PathBuf::from(value));
+        } else if let Some(bind) = flags.iter_mut().find(|(k, _)| key == "bind" && k == "bind") {
+            bind.1.push(',');
+            bind.1.push_str(&value);
+        } else {
+            flags.push((key, value));
+        }
+    }
+    Ok(Args {
+        config: config_path,
+        flags,
+    })
+}
+
+#[test]
+fn test_config() {
+    // a config file of its own, so that the working directory does not matter
+    let dir = std::env::temp_dir().join(format!("reversi-config-{}", std::process::id()));
+    fs::create_dir_all(dir.join("static")).unwrap();
+    let path = dir.join("reversi.toml");
+    fs::write(
+        &path,
+        format!(
+            "static_root = {:?}\nclient_timeout = 30\n",
+            dir.join("static")
+        ),
+    )
+    .unwrap();
+
+    let strings = |v: &[&str]| {
+        let mut args = vec!["--config".to_owned(), path.display().to_string()];
+        args.extend(v.iter().map(|s| s.to_string()));
+        args
+    };
+    let env = |v: &[(&str, &str)]| {
+        v.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<(String, String)>>()
+    };
+
+    let config = Config::load(
+        strings(&[
+            "--bind",
+            "127.0.0.1:9000",
+            "--bind=[::1]:9000",
+            "--max-rooms",
+            "10",
+        ]),
+        env(&[
+            ("REVERSI_MAX_ROOMS", "20"),
+            ("REVERSI_LOG_LEVEL", "debug"),
+            ("REVERSI_COLOUR", "red"),
+            ("HOME", "/root"),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(config.bind, vec!["127.0.0.1:9000", "[::1]:9000"]);
+    assert_eq!(config.client_timeout, 30);
+    // flags win over the environment
+    assert_eq!(config.max_rooms, 10);
+    assert_eq!(config.log_level, "debug");
+    assert_eq!(config.heartbeat_interval(), Duration::from_secs(5));
+    // unknown variables are only warned about
+    assert_eq!(config.unknown_env, vec!["REVERSI_COLOUR"]);
+
+    let config: Config = toml::from_str("client_timeout = 30\nbind = [\"0.0.0.0:80\"]").unwrap();
+    assert_eq!(config.client_timeout, 30);
+    assert_eq!(config.static_root, Config::default().static_root);
+    assert!(toml::from_str::<Config>("max_room = 1").is_err());
+
+    let nowhere = dir.join("nowhere").display().to_string();
+    let errors = Config::load(
+        strings(&["--heartbeat-interval", "40", "--static-root", &nowhere]),
+        env(&[("REVERSI_LOG_LEVEL", "loud")]),
+    )
+    .unwrap_err();
+    assert_eq!(errors.len(), 3);
+    assert_eq!(
+        Config::load(strings(&["--max-rooms", "many"]), env(&[])).unwrap_err(),
+        vec![ConfigError(
+            "--max-rooms: \"many\" is not a number".to_owned()
+        )]
+    );
+    assert!(Config::load(strings(&["--colour", "red"]), env(&[])).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,6 @@
+extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+pub mod reversi;
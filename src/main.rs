@@ -1,27 +1,62 @@
 #![allow(unused_variables)]
+extern crate argon2;
 extern crate byteorder;
 extern crate bytes;
 extern crate env_logger;
 extern crate futures;
 extern crate rand;
+extern crate rusqlite;
 extern crate serde;
 extern crate serde_json;
 extern crate tokio_core;
 extern crate tokio_io;
+extern crate toml;
+extern crate url;
 
+#[macro_use]
+extern crate log;
 #[macro_use]
 extern crate serde_derive;
 
 extern crate actix;
 extern crate actix_web;
+extern crate websocket_reversi_example;
 
+mod account;
 mod app;
+mod clock;
+mod config;
 mod message;
-mod reversi;
+mod protocol;
+mod rating;
 mod server;
+mod storage;
+mod worker;
+
+use websocket_reversi_example::reversi;
 
 use app::App;
+use config::Config;
+use std::env;
+use std::process;
 
 fn main() {
-    App::start();
+    if env::args().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", config::USAGE);
+        return;
+    }
+    let config = match Config::load(env::args().skip(1), env::vars()) {
+        Ok(config) => config,
+        Err(errors) => {
+            for e in errors {
+                eprintln!("Invalid config: {}", e);
+            }
+            eprintln!("\n{}", config::USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = App::start(config) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
 }
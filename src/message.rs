@@ -1,8 +1,9 @@
+use account::Credentials;
 use actix::prelude::*;
-use server::Room;
-
-#[derive(Message)]
-pub struct Message(pub String);
+use rating::Rating;
+use reversi::ai::{Analysis, Level};
+use server::{ReversiError, Room, RoomDetail, RoomSummary};
+use storage::{ArchivedGame, GameId, StorageError, Timestamp};
 
 #[derive(Message)]
 #[rtype(usize)]
@@ -27,9 +28,155 @@ impl actix::Message for ListRooms {
     type Result = Vec<(String, Room)>;
 }
 
-#[derive(Message)]
+/// Public rooms, sorted by name
+pub struct RoomSummaries;
+
+impl actix::Message for RoomSummaries {
+    type Result = Vec<RoomSummary>;
+}
+
+/// Board, turn and players of a public room
+pub struct RoomDetails {
+    pub name: String,
+}
+
+impl actix::Message for RoomDetails {
+    type Result = Result<RoomDetail, ReversiError>;
+}
+
+/// An archived game
+pub struct GetGame {
+    pub id: GameId,
+}
+
+impl actix::Message for GetGame {
+    type Result = Result<Option<ArchivedGame>, StorageError>;
+}
+
+/// Archived games of a player, finished in a time range, or both
+#[derive(Deserialize)]
+pub struct FindGames {
+    pub player: Option<String>,
+    /// Unix time in seconds
+    pub from: Option<Timestamp>,
+    pub to: Option<Timestamp>,
+}
+
+impl actix::Message for FindGames {
+    type Result = Result<Vec<ArchivedGame>, ReversiError>;
+}
+
+/// Evaluate a position, see `Game::from_position_string`
+#[derive(Deserialize)]
+pub struct Analyze {
+    pub position: String,
+    /// Search depth, `Level::default()` if omitted
+    #[serde(default)]
+    pub level: Option<Level>,
+}
+
+impl actix::Message for Analyze {
+    type Result = Result<Analysis, ReversiError>;
+}
+
+/// Get `RoomCreated`, `RoomUpdated` and `RoomClosed` events, starting
+/// with a `RoomCreated` for every public room
+pub struct SubscribeLobby {
+    pub uid: usize,
+}
+
+impl actix::Message for SubscribeLobby {
+    type Result = Result<(), ReversiError>;
+}
+
+pub struct UnsubscribeLobby {
+    pub uid: usize,
+}
+
+impl actix::Message for UnsubscribeLobby {
+    type Result = Result<(), ReversiError>;
+}
+
+/// The best rated players
+pub struct Leaderboard {
+    pub limit: usize,
+}
+
+impl actix::Message for Leaderboard {
+    type Result = Result<Vec<Rating>, ReversiError>;
+}
+
 pub struct Join {
     pub name: String,
     pub uid: usize,
     pub uname: String,
+    pub password: Option<String>,
+    /// One-time invite code
+    pub code: Option<String>,
+}
+
+impl actix::Message for Join {
+    type Result = Result<(), ReversiError>;
+}
+
+/// Enter a room as a spectator
+pub struct Watch {
+    pub name: String,
+    pub uid: usize,
+    pub password: Option<String>,
+    /// One-time invite code
+    pub code: Option<String>,
+}
+
+impl actix::Message for Watch {
+    type Result = Result<(), ReversiError>;
+}
+
+/// Take back a seat held after a disconnection, answered with the room name
+pub struct Resume {
+    pub token: String,
+    pub uid: usize,
+}
+
+impl actix::Message for Resume {
+    type Result = Result<String, ReversiError>;
+}
+
+/// Create an account and log in
+pub struct Register {
+    pub credentials: Credentials,
+}
+
+impl actix::Message for Register {
+    type Result = Result<String, ReversiError>;
+}
+
+/// Check a password and issue a session token
+pub struct Login {
+    pub credentials: Credentials,
+}
+
+impl actix::Message for Login {
+    type Result = Result<String, ReversiError>;
+}
+
+/// Revoke a session token. Connections already authenticated with it stay
+/// logged in until they close.
+#[derive(Deserialize)]
+pub struct Logout {
+    pub token: String,
+}
+
+impl actix::Message for Logout {
+    type Result = Result<(), ReversiError>;
+}
+
+/// Attach the account a session token was issued for to a websocket
+pub struct Authenticate {
+    pub token: String,
+    pub uid: usize,
+}
+
+impl actix::Message for Authenticate {
+    type Result = Result<String, ReversiError>;
 }
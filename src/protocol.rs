@@ -0,0 +1,478 @@
+use clock::TimeControl;
+use reversi::ai::Level;
+use reversi::board::{Color, Pos};
+use server::ReversiError;
+use std::str::FromStr;
+
+/// Request ids are chosen by the client and copied into the reply.
+pub type RequestId = u64;
+
+/// Commands sent by clients, as JSON objects tagged with their `type`:
+///
+/// ```text
+/// {"type":"MakeRoom","id":1,"room":"Shiba","name":"pipopa","color":"Black"}
+/// {"type":"Move","id":2,"color":"Black","x":5,"y":4}
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum ClientMessage {
+    ListRooms,
+    /// Follow room changes as they happen
+    SubscribeLobby,
+    UnsubscribeLobby,
+    Leaderboard,
+    MakeRoom {
+        room: String,
+        name: String,
+        color: Option<Color>,
+        /// Play against a computer of this level
+        opponent: Option<Level>,
+        time_control: Option<TimeControl>,
+        /// Hide the room from `ListRooms`
+        #[serde(default)]
+        private: bool,
+        password: Option<String>,
+    },
+    Join {
+        room: String,
+        name: String,
+        password: Option<String>,
+        /// One-time code from an invite
+        code: Option<String>,
+    },
+    Watch {
+        room: String,
+        password: Option<String>,
+        code: Option<String>,
+    },
+    /// Get an invite code for one's room
+    Invite,
+    /// Say something in one's room, or in the lobby
+    Chat {
+        text: String,
+        #[serde(default)]
+        lobby: bool,
+    },
+    Resume {
+        token: String,
+    },
+    /// Log the connection in with a session token from `/login`
+    Authenticate {
+        token: String,
+    },
+    /// Wait to be paired with another player
+    Queue {
+        name: String,
+        time_control: Option<TimeControl>,
+        /// Only accept opponents rated within this many points
+        band: Option<u32>,
+    },
+    Unqueue,
+    Move {
+        color: Color,
+        x: usize,
+        y: usize,
+    },
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    /// Call the game off before the first move
+    Abort,
+    /// Ask the opponent to take back one's last move
+    Takeback,
+    AcceptTakeback,
+    DeclineTakeback,
+    /// Play again in the same room once the game is over
+    Rematch,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    /// `None` for legacy commands and JSON requests without an `id`
+    pub id: Option<RequestId>,
+    pub message: ClientMessage,
+    /// Whether the request used the slash syntax, whose replies are plain
+    /// text
+    pub legacy: bool,
+}
+
+fn invalid<S: Into<String>>(message: S) -> ReversiError {
+    ReversiError::InvalidRequest(message.into())
+}
+
+impl ClientMessage {
+    /// Checks what the types alone cannot.
+    fn validate(self) -> Result<ClientMessage, ReversiError> {
+        match self {
+            ClientMessage::MakeRoom {
+                opponent: Some(level),
+                ..
+            } if level.0 > Level::MAX.0 => Err(invalid(format!(
+                "Level must be between 0 and {}",
+                Level::MAX
+            ))),
+            ClientMessage::MakeRoom {
+                time_control: Some(control),
+                ..
+            }
+            | ClientMessage::Queue {
+                time_control: Some(control),
+                ..
+            } => match control.validate() {
+                Ok(()) => Ok(self),
+                Err(e) => Err(invalid(e)),
+            },
+            ClientMessage::Move { x, y, .. } if x >= 8 || y >= 8 => {
+                Err(invalid(format!("square out of range: {} {}", x, y)))
+            }
+            message => Ok(message),
+        }
+    }
+}
+
+impl Request {
+    /// Reads a request in either syntax. On failure the request id is
+    /// returned along with the error if it could be read.
+    pub fn parse(text: &str) -> Result<Request, (Option<RequestId>, ReversiError)> {
+        let text = text.trim();
+        if text.starts_with('/') {
+            let message = parse_legacy(text).map_err(|e| (None, e))?;
+            return Ok(Request {
+                id: None,
+                message,
+                legacy: true,
+            });
+        }
+        // chat goes through /chat, plain text is not a request
+        if !text.starts_with('{') {
+            return Err((None, invalid(format!("unknown command: {:?}", text))));
+        }
+
+        let value: serde_json::Value =
+            serde_json::from_str(text).map_err(|e| (None, invalid(e.to_string())))?;
+        let id = value.get("id").and_then(|id| id.as_u64());
+        let message = serde_json::from_value::<ClientMessage>(value)
+            .map_err(|e| invalid(e.to_string()))
+            .and_then(ClientMessage::validate)
+            .map_err(|e| (id, e))?;
+        Ok(Request {
+            id,
+            message,
+            legacy: false,
+        })
+    }
+}
+
+/// Slash commands, kept for existing scripts:
+///
+/// ```text
+/// /listRooms
+/// /subscribeLobby
+/// /unsubscribeLobby
+/// /leaderboard
+/// /makeRoom <room> <name> [black|white] [cpu[:level]] [fischer:<base>+<increment>|byoyomi:<base>+<period>] [private] [password:<password>]
+/// /join <room> <name> [password:<password>|code:<code>]
+/// /watch <room> [password:<password>|code:<code>]
+/// /invite
+/// /chat <text>
+/// /lobbyChat <text>
+/// /resume <token>
+/// /authenticate <token>
+/// /queue <name> [fischer:<base>+<increment>|byoyomi:<base>+<period>] [band:<points>]
+/// /unqueue
+/// /move <color> <x> <y>
+/// /move <color> <square>
+/// /resign
+/// /offerDraw
+/// /acceptDraw
+/// /abort
+/// /takeback
+/// /acceptTakeback
+/// /declineTakeback
+/// /rematch
+/// ```
+pub fn parse_legacy(m: &str) -> Result<ClientMessage, ReversiError> {
+    let v: Vec<&str> = m.splitn(4, ' ').collect();
+    match v[0] {
+        "/listRooms" => Ok(ClientMessage::ListRooms),
+        "/subscribeLobby" => Ok(ClientMessage::SubscribeLobby),
+        "/unsubscribeLobby" => Ok(ClientMessage::UnsubscribeLobby),
+        "/leaderboard" => Ok(ClientMessage::Leaderboard),
+        "/resign" => Ok(ClientMessage::Resign),
+        "/offerDraw" => Ok(ClientMessage::OfferDraw),
+        "/acceptDraw" => Ok(ClientMessage::AcceptDraw),
+        "/abort" => Ok(ClientMessage::Abort),
+        "/unqueue" => Ok(ClientMessage::Unqueue),
+        "/takeback" => Ok(ClientMessage::Takeback),
+        "/acceptTakeback" => Ok(ClientMessage::AcceptTakeback),
+        "/declineTakeback" => Ok(ClientMessage::DeclineTakeback),
+        "/rematch" => Ok(ClientMessage::Rematch),
+        "/invite" => Ok(ClientMessage::Invite),
+        "/chat" | "/lobbyChat" => Ok(ClientMessage::Chat {
+            text: m[v[0].len()..].trim().to_owned(),
+            lobby: v[0] == "/lobbyChat",
+        }),
+        "/join" => {
+            if v.len() < 3 {
+                return Err(invalid("room name is required"));
+            }
+            let (password, code) = parse_key(v.get(3).unwrap_or(&""))?;
+            Ok(ClientMessage::Join {
+                room: v[1].to_owned(),
+                name: v[2].to_owned(),
+                password,
+                code,
+            })
+        }
+        "/watch" => {
+            if v.len() < 2 {
+                return Err(invalid("room name is required"));
+            }
+            let (password, code) = parse_key(&v[2..].join(" "))?;
+            Ok(ClientMessage::Watch {
+                room: v[1].to_owned(),
+                password,
+                code,
+            })
+        }
+        "/authenticate" => {
+            if v.len() == 2 {
+                Ok(ClientMessage::Authenticate {
+                    token: v[1].to_owned(),
+                })
+            } else {
+                Err(invalid("session token is required"))
+            }
+        }
+        "/resume" => {
+            if v.len() == 2 {
+                Ok(ClientMessage::Resume {
+                    token: v[1].to_owned(),
+                })
+            } else {
+                Err(invalid("resume token is required"))
+            }
+        }
+        "/queue" => {
+            if v.len() < 2 {
+                return Err(invalid("name is required"));
+            }
+            let mut time_control = None;
+            let mut band = None;
+            for option in v[2..].join(" ").split_whitespace() {
+                if let Some(points) = option.strip_prefix("band:") {
+                    band = Some(
+                        points
+                            .parse()
+                            .map_err(|_| invalid(format!("invalid band: {}", points)))?,
+                    );
+                } else if option.starts_with("fischer:") || option.starts_with("byoyomi:") {
+                    time_control = Some(option.parse().map_err(invalid)?);
+                } else {
+                    return Err(invalid(format!("unknown option: {}", option)));
+                }
+            }
+            Ok(ClientMessage::Queue {
+                name: v[1].to_owned(),
+                time_control,
+                band,
+            })
+        }
+        "/makeRoom" => {
+            if v.len() < 3 {
+                return Err(invalid("room name is required"));
+            }
+            let mut color = None;
+            let mut opponent = None;
+            let mut time_control = None;
+            let mut private = false;
+            let mut password = None;
+            for option in v.get(3).unwrap_or(&"").split_whitespace() {
+                if option == "private" {
+                    private = true;
+                } else if let Some(p) = option.strip_prefix("password:") {
+                    password = Some(p.to_owned());
+                } else if option == "cpu" {
+                    opponent = Some(Level::default());
+                } else if let Some(level) = option.strip_prefix("cpu:") {
+                    opponent = Some(level.parse().map_err(invalid)?);
+                } else if option.starts_with("fischer:") || option.starts_with("byoyomi:") {
+                    time_control = Some(option.parse().map_err(invalid)?);
+                } else if let Ok(c) = Color::from_str(option) {
+                    color = Some(c);
+                } else {
+                    return Err(invalid(format!("unknown option: {}", option)));
+                }
+            }
+            Ok(ClientMessage::MakeRoom {
+                room: v[1].to_owned(),
+                name: v[2].to_owned(),
+                color,
+                opponent,
+                time_control,
+                private,
+                password,
+            })
+        }
+        "/move" => {
+            if v.len() < 3 {
+                return Err(invalid("color is required"));
+            }
+            let color = if v[1].to_uppercase() == "BLACK" {
+                Color::Black
+            } else {
+                Color::White
+            };
+            let (x, y) = if v.len() == 4 {
+                match (v[2].parse(), v[3].parse()) {
+                    (Ok(x), Ok(y)) => (x, y),
+                    _ => return Err(invalid(format!("invalid square: {} {}", v[2], v[3]))),
+                }
+            } else {
+                let p: Pos<usize> = v[2].parse().map_err(|e| invalid(format!("{}", e)))?;
+                (p.x, p.y)
+            };
+            ClientMessage::Move { color, x, y }.validate()
+        }
+        _ => Err(invalid(format!("unknown command: {:?}", m))),
+    }
+}
+
+/// `password:<password>` or `code:<code>` after `/join` and `/watch`
+fn parse_key(options: &str) -> Result<(Option<String>, Option<String>), ReversiError> {
+    let mut password = None;
+    let mut code = None;
+    for option in options.split_whitespace() {
+        if let Some(p) = option.strip_prefix("password:") {
+            password = Some(p.to_owned());
+        } else if let Some(c) = option.strip_prefix("code:") {
+            code = Some(c.to_owned());
+        } else {
+            return Err(invalid(format!("unknown option: {}", option)));
+        }
+    }
+    Ok((password, code))
+}
+
+#[test]
+fn test_parse_request() {
+    let request = Request::parse(
+        r#"{"type":"MakeRoom","id":1,"room":"Shiba","name":"pipopa","color":"Black","opponent":2}"#,
+    )
+    .unwrap();
+    assert_eq!(request.id, Some(1));
+    assert!(!request.legacy);
+    assert_eq!(
+        request.message,
+        ClientMessage::MakeRoom {
+            room: "Shiba".to_owned(),
+            name: "pipopa".to_owned(),
+            color: Some(Color::Black),
+            opponent: Some(Level(2)),
+            time_control: None,
+            private: false,
+            password: None,
+        }
+    );
+    assert_eq!(
+        Request::parse(r#"{"type":"ListRooms"}"#).unwrap().message,
+        ClientMessage::ListRooms
+    );
+
+    let (id, _) = Request::parse(r#"{"type":"Fly","id":7}"#).unwrap_err();
+    assert_eq!(id, Some(7));
+    let (id, _) = Request::parse("{not json").unwrap_err();
+    assert_eq!(id, None);
+    assert_eq!(
+        Request::parse("hello").unwrap_err(),
+        (None, invalid("unknown command: \"hello\""))
+    );
+    assert_eq!(
+        Request::parse(r#"{"type":"Move","id":3,"color":"White","x":8,"y":0}"#).unwrap_err(),
+        (Some(3), invalid("square out of range: 8 0"))
+    );
+}
+
+#[test]
+fn test_parse_legacy() {
+    let request = Request::parse("/move black 5 4").unwrap();
+    assert!(request.legacy);
+    assert_eq!(
+        request.message,
+        ClientMessage::Move {
+            color: Color::Black,
+            x: 5,
+            y: 4,
+        }
+    );
+    assert_eq!(
+        parse_legacy("/move white f5"),
+        Ok(ClientMessage::Move {
+            color: Color::White,
+            x: 5,
+            y: 4,
+        })
+    );
+    assert_eq!(
+        parse_legacy("/makeRoom Solo pipopa white cpu:3 byoyomi:0+30"),
+        Ok(ClientMessage::MakeRoom {
+            room: "Solo".to_owned(),
+            name: "pipopa".to_owned(),
+            color: Some(Color::White),
+            opponent: Some(Level(3)),
+            time_control: Some(TimeControl::Byoyomi {
+                base: 0,
+                period: 30
+            }),
+            private: false,
+            password: None,
+        })
+    );
+    assert_eq!(
+        parse_legacy("/makeRoom Solo pipopa fischer:0+5"),
+        Err(invalid("base time must not be 0"))
+    );
+
+    // these used to panic the session
+    assert_eq!(
+        parse_legacy("/move black x 4"),
+        Err(invalid("invalid square: x 4"))
+    );
+    assert_eq!(
+        parse_legacy("/move black 9 4"),
+        Err(invalid("square out of range: 9 4"))
+    );
+    assert_eq!(parse_legacy("/offerDraw"), Ok(ClientMessage::OfferDraw));
+    assert_eq!(
+        parse_legacy("/chat good  game"),
+        Ok(ClientMessage::Chat {
+            text: "good  game".to_owned(),
+            lobby: false,
+        })
+    );
+    assert_eq!(
+        parse_legacy("/join Shiba Tatsuo code:0123abcd"),
+        Ok(ClientMessage::Join {
+            room: "Shiba".to_owned(),
+            name: "Tatsuo".to_owned(),
+            password: None,
+            code: Some("0123abcd".to_owned()),
+        })
+    );
+    assert_eq!(
+        parse_legacy("/queue pipopa fischer:60+1 band:200"),
+        Ok(ClientMessage::Queue {
+            name: "pipopa".to_owned(),
+            time_control: Some(TimeControl::Fischer {
+                base: 60,
+                increment: 1
+            }),
+            band: Some(200),
+        })
+    );
+    assert_eq!(parse_legacy("/join"), Err(invalid("room name is required")));
+    assert_eq!(
+        parse_legacy("/dance"),
+        Err(invalid("unknown command: \"/dance\""))
+    );
+}
@@ -0,0 +1,64 @@
+use reversi::board::Color;
+use reversi::game::Winner;
+
+/// Rating of players who have not finished a rated game yet
+pub const INITIAL_RATING: f64 = 1500.0;
+/// How far a single game moves a rating
+const K_FACTOR: f64 = 32.0;
+
+/// Elo rating of a player.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Rating {
+    pub name: String,
+    pub rating: f64,
+    /// Rated games played
+    pub games: u32,
+}
+
+impl Rating {
+    pub fn new(name: &str) -> Self {
+        Rating {
+            name: name.to_owned(),
+            rating: INITIAL_RATING,
+            games: 0,
+        }
+    }
+}
+
+/// Expected score of a player rated `rating` against `opponent`, between 0
+/// and 1.
+pub fn expected_score(rating: f64, opponent: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((opponent - rating) / 400.0))
+}
+
+/// Updates both ratings after a game between them.
+pub fn rate_game(black: &mut Rating, white: &mut Rating, winner: Winner) {
+    let score = match winner {
+        Some(Color::Black) => 1.0,
+        Some(Color::White) => 0.0,
+        None => 0.5,
+    };
+    let change = K_FACTOR * (score - expected_score(black.rating, white.rating));
+    black.rating += change;
+    white.rating -= change;
+    black.games += 1;
+    white.games += 1;
+}
+
+#[test]
+fn test_rate_game() {
+    assert_eq!(expected_score(1500.0, 1500.0), 0.5);
+    assert!((expected_score(1900.0, 1500.0) - 0.909).abs() < 0.001);
+
+    let mut black = Rating::new("pipopa");
+    let mut white = Rating::new("Tatsuo");
+    rate_game(&mut black, &mut white, Some(Color::White));
+    assert_eq!(black.rating, 1484.0);
+    assert_eq!(white.rating, 1516.0);
+    assert_eq!(black.games, 1);
+
+    // a draw against a weaker player costs points
+    rate_game(&mut black, &mut white, None);
+    assert!(white.rating < 1516.0);
+    assert!((black.rating + white.rating - 3000.0).abs() < 1e-9);
+}
@@ -0,0 +1,122 @@
+use rand::Rng;
+use reversi::board::{Move, Pos};
+use reversi::engine::{Engine, Limit};
+use reversi::game::Game;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Upper bound on the time a computer player thinks about a move.
+const THINKING_TIME: Duration = Duration::from_secs(1);
+/// Upper bound on the time spent on one `analyze`.
+const ANALYSIS_TIME: Duration = Duration::from_millis(200);
+
+/// Strength of a computer player.
+///
+/// Level 0 plays a random legal move, higher levels search that many plies
+/// ahead with the `engine`.
+#[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Debug)]
+pub struct Level(pub u8);
+
+impl Level {
+    pub const MAX: Level = Level(6);
+}
+
+impl Default for Level {
+    fn default() -> Self {
+        Level(1)
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<u8>() {
+            Ok(n) if n <= Level::MAX.0 => Ok(Level(n)),
+            _ => Err(format!("Level must be between 0 and {}", Level::MAX)),
+        }
+    }
+}
+
+/// Chooses a move for the side to move, or `None` if the game is over.
+pub fn choose_move<R: Rng>(game: &Game, level: Level, rng: &mut R) -> Option<Move> {
+    if level.0 == 0 {
+        let moves = game.available_pos();
+        let p = rng.choose(&moves)?;
+        return Some(Move {
+            x: p.x,
+            y: p.y,
+            color: game.turn,
+        });
+    }
+    Engine::default().search(game, limit(level)).best_move
+}
+
+fn limit(level: Level) -> Limit {
+    Limit {
+        depth: level.0.max(1),
+        time: Some(THINKING_TIME),
+    }
+}
+
+/// Legal moves and the engine's view of a position. Every square is written
+/// like `d3`, whichever side plays it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Analysis {
+    /// Squares the side to move can play
+    pub legal_moves: Vec<String>,
+    /// `None` if the game is over
+    pub best_move: Option<String>,
+    /// From the point of view of the side to move
+    pub score: i32,
+    /// Principal variation, starting with `best_move`
+    pub pv: Vec<String>,
+    pub depth: u8,
+}
+
+/// Searches `game` as deep as a computer player of `level` would, but for
+/// no longer than `ANALYSIS_TIME`.
+pub fn analyze(game: &Game, level: Level) -> Analysis {
+    let limit = Limit {
+        time: Some(ANALYSIS_TIME),
+        ..limit(level)
+    };
+    let result = Engine::default().search(game, limit);
+    let square = |m: &Move| Pos { x: m.x, y: m.y }.to_string();
+    Analysis {
+        legal_moves: game.available_pos().iter().map(|p| p.to_string()).collect(),
+        best_move: result.best_move.as_ref().map(square),
+        score: result.score,
+        pv: result.pv.iter().map(square).collect(),
+        depth: result.depth,
+    }
+}
+
+#[test]
+fn test_choose_move() {
+    use reversi::board::{Board, Color};
+
+    let mut rng = rand::thread_rng();
+    let mut game = Game::new();
+    for level in 0..=Level::MAX.0 {
+        let m = choose_move(&game, Level(level), &mut rng).unwrap();
+        assert!(game.can_put(Pos { x: m.x, y: m.y }, Color::Black));
+    }
+
+    // a1 is a corner and must be preferred over everything else
+    game.board = Board::from_bits(1 << 2, 1 << 1 | 1 << 3);
+    let m = choose_move(&game, Level(1), &mut rng).unwrap();
+    assert_eq!((m.x, m.y), (0, 0));
+
+    let analysis = analyze(&game, Level(1));
+    assert_eq!(analysis.best_move.as_deref(), Some("a1"));
+    assert_eq!(analysis.pv[0], "a1");
+    assert_eq!(analysis.legal_moves, vec!["a1", "e1"]);
+}
@@ -1,8 +1,8 @@
 use std::fmt;
-use std::ops::{Add, Deref};
+use std::ops::Add;
 use std::str::FromStr;
 
-#[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Hash, Debug)]
 pub enum Color {
     Black,
     White,
@@ -14,16 +14,19 @@ impl Color {
     }
 
     pub fn is_black(&self) -> bool {
-        use self::Color;
-        match *self {
-            Color::Black => true,
-            _ => false,
-        }
+        matches!(*self, Color::Black)
     }
 
     pub fn is_white(&self) -> bool {
         !self.is_black()
     }
+
+    pub fn opposite(self) -> Color {
+        match self {
+            Color::Black => Color::White,
+            Color::White => Color::Black,
+        }
+    }
 }
 
 impl FromStr for Color {
@@ -48,27 +51,15 @@ pub enum Cell {
 
 impl Cell {
     pub fn is_empty(&self) -> bool {
-        use self::Cell::Empty;
-        match *self {
-            Empty => true,
-            _ => false,
-        }
+        matches!(*self, Cell::Empty)
     }
 
     pub fn is_available(&self) -> bool {
-        use self::Cell::Available;
-        match *self {
-            Available => true,
-            _ => false,
-        }
+        matches!(*self, Cell::Available)
     }
 
     pub fn is_piece(&self) -> bool {
-        use self::Cell::Piece;
-        match *self {
-            Piece(_) => true,
-            _ => false,
-        }
+        matches!(*self, Cell::Piece(_))
     }
 }
 
@@ -86,11 +77,11 @@ impl fmt::Display for Cell {
             Available => "available",
             Empty => "empty",
         };
-        write!(f, "{}", s.to_string())
+        write!(f, "{}", s)
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct Pos<T> {
     pub x: T,
     pub y: T,
@@ -102,7 +93,7 @@ impl Add for Pos<i32> {
     fn add(self, other: Pos<i32>) -> Self::Output {
         let x = self.x + other.x;
         let y = self.y + other.y;
-        if x < 0 || x > 8 || y < 0 || y > 8 {
+        if !(0..8).contains(&x) || !(0..8).contains(&y) {
             return None;
         }
         let p = Pos {
@@ -119,7 +110,7 @@ impl Add<Pos<i32>> for Pos<usize> {
     fn add(self, other: Pos<i32>) -> Self::Output {
         let x = self.x as i32 + other.x;
         let y = self.y as i32 + other.y;
-        if x < 0 || x > 7 || y < 0 || y > 7 {
+        if !(0..8).contains(&x) || !(0..8).contains(&y) {
             return None;
         }
         let p = Pos {
@@ -130,46 +121,78 @@ impl Add<Pos<i32>> for Pos<usize> {
     }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
 pub struct Move {
     pub x: usize,
     pub y: usize,
     pub color: Color,
 }
 
-impl Move {
-    fn new(x: i32, y: i32, color: Color) -> Result<Self, String> {
-        if x < 0 || x > 8 || y < 0 || y > 8 {
-            return Err("Invalid Pos".to_string());
-        }
-        Ok(Move {
-            x: x as usize,
-            y: y as usize,
-            color,
-        })
-    }
-}
-
-#[derive(Copy, Clone)]
-pub struct Board([Cell; 64]);
+/// Every square except the A file (x == 0)
+const NOT_A_FILE: u64 = 0xfefe_fefe_fefe_fefe;
+/// Every square except the H file (x == 7)
+const NOT_H_FILE: u64 = 0x7f7f_7f7f_7f7f_7f7f;
 
-impl Deref for Board {
-    type Target = [Cell; 64];
+/// Shift distances and wrap-around masks for the eight directions.
+/// A positive shift moves towards higher bit indices (`y * 8 + x`).
+const SHIFTS: [(i32, u64); 8] = [
+    (1, NOT_A_FILE),
+    (9, NOT_A_FILE),
+    (8, !0),
+    (7, NOT_H_FILE),
+    (-1, NOT_H_FILE),
+    (-9, NOT_H_FILE),
+    (-8, !0),
+    (-7, NOT_A_FILE),
+];
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+#[inline]
+fn shift(bits: u64, (n, mask): (i32, u64)) -> u64 {
+    if n > 0 {
+        (bits << n) & mask
+    } else {
+        (bits >> -n) & mask
     }
 }
 
+/// Bitboard representation of the discs on the board.
+///
+/// Bit `y * 8 + x` of `black` / `white` is set when that square holds a disc
+/// of the corresponding color. Legal moves are never stored; they are derived
+/// from the two bitboards with `legal_moves`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub struct Board {
+    black: u64,
+    white: u64,
+}
+
 impl fmt::Debug for Board {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        self.0[..].fmt(formatter)
+        formatter
+            .debug_struct("Board")
+            .field("black", &format_args!("{:#018x}", self.black))
+            .field("white", &format_args!("{:#018x}", self.white))
+            .finish()
     }
 }
 
 impl Board {
     pub fn new() -> Self {
-        Board([self::Cell::Empty; 64])
+        Board::default()
+    }
+
+    pub fn from_bits(black: u64, white: u64) -> Self {
+        debug_assert_eq!(black & white, 0);
+        Board { black, white }
+    }
+
+    pub fn bit(p: Pos<usize>) -> u64 {
+        1 << (p.y * 8 + p.x)
+    }
+
+    pub fn pos_of(bit: u64) -> Pos<usize> {
+        let i = bit.trailing_zeros() as usize;
+        Pos { x: i % 8, y: i / 8 }
     }
 
     pub fn all_pos() -> Vec<Pos<usize>> {
@@ -182,41 +205,114 @@ impl Board {
         v
     }
 
+    pub fn discs(&self, color: Color) -> u64 {
+        match color {
+            Color::Black => self.black,
+            Color::White => self.white,
+        }
+    }
+
+    pub fn occupied(&self) -> u64 {
+        self.black | self.white
+    }
+
+    pub fn empties(&self) -> u64 {
+        !self.occupied()
+    }
+
+    /// Never returns `Cell::Available`; see `Game::cells` for a view that
+    /// includes the squares playable by the side to move.
     pub fn get_cell(&self, p: Pos<usize>) -> Cell {
-        self.0[p.y * 8 + p.x]
+        let bit = Board::bit(p);
+        if self.black & bit != 0 {
+            Cell::Piece(Color::Black)
+        } else if self.white & bit != 0 {
+            Cell::Piece(Color::White)
+        } else {
+            Cell::Empty
+        }
     }
 
+    /// `Cell::Available` is treated as `Cell::Empty`.
     pub fn set_cell(&mut self, p: Pos<usize>, cell: Cell) {
-        self.0[p.y * 8 + p.x] = cell;
-    }
-
-    pub fn count_piece(&self) -> (usize, usize, usize, usize) {
-        let mut black = 0;
-        let mut white = 0;
-        let mut available = 0;
-        let mut empty = 0;
-        for p in self.0.iter() {
-            match p {
-                Cell::Piece(color) => {
-                    if color.is_black() {
-                        black += 1;
-                    } else {
-                        white += 1;
-                    }
-                }
-                Cell::Available => {
-                    available += 1;
-                }
-                Cell::Empty => {
-                    empty += 1;
-                }
+        let bit = Board::bit(p);
+        self.black &= !bit;
+        self.white &= !bit;
+        match cell {
+            Cell::Piece(Color::Black) => self.black |= bit,
+            Cell::Piece(Color::White) => self.white |= bit,
+            _ => {}
+        }
+    }
+
+    /// (black, white, empty)
+    pub fn count_piece(&self) -> (usize, usize, usize) {
+        let black = self.black.count_ones() as usize;
+        let white = self.white.count_ones() as usize;
+        (black, white, 64 - black - white)
+    }
+
+    /// Squares where `color` can legally place a disc.
+    pub fn legal_moves(&self, color: Color) -> u64 {
+        let player = self.discs(color);
+        let opponent = self.discs(color.opposite());
+        let empty = self.empties();
+        let mut moves = 0;
+        for &dir in &SHIFTS {
+            let mut x = shift(player, dir) & opponent;
+            for _ in 0..5 {
+                x |= shift(x, dir) & opponent;
             }
+            moves |= shift(x, dir) & empty;
         }
-        (black, white, available, empty)
+        moves
+    }
+
+    pub fn has_legal_move(&self, color: Color) -> bool {
+        self.legal_moves(color) != 0
     }
 
-    pub fn has_available_cell(&self) -> bool {
-        self.0.contains(&Cell::Available)
+    /// Discs that would be flipped if `color` played on `bit`.
+    /// Returns 0 when the move flips nothing, i.e. is illegal.
+    pub fn flips(&self, color: Color, bit: u64) -> u64 {
+        if self.occupied() & bit != 0 {
+            return 0;
+        }
+        self.bracketed(color, bit)
+    }
+
+    /// Opponent discs bracketed between `bit` and another `color` disc,
+    /// regardless of whether `bit` itself is occupied.
+    pub fn bracketed(&self, color: Color, bit: u64) -> u64 {
+        let player = self.discs(color);
+        let opponent = self.discs(color.opposite());
+        let mut flips = 0;
+        for &dir in &SHIFTS {
+            let mut line = 0;
+            let mut x = shift(bit, dir);
+            while x & opponent != 0 {
+                line |= x;
+                x = shift(x, dir);
+            }
+            if x & player != 0 {
+                flips |= line;
+            }
+        }
+        flips
+    }
+
+    /// Places a `color` disc on `bit` and flips `flips`, as returned by `flips`.
+    pub fn apply(&mut self, color: Color, bit: u64, flips: u64) {
+        match color {
+            Color::Black => {
+                self.black |= bit | flips;
+                self.white &= !flips;
+            }
+            Color::White => {
+                self.white |= bit | flips;
+                self.black &= !flips;
+            }
+        }
     }
 
     pub fn show(&self) {
@@ -230,7 +326,23 @@ impl Board {
                 };
                 print!("{}", s);
             }
-            println!("");
+            println!();
+        }
+    }
+}
+
+/// Iterates over the set bits of a bitboard, lowest first.
+pub struct Bits(pub u64);
+
+impl Iterator for Bits {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.0 == 0 {
+            return None;
         }
+        let bit = self.0 & self.0.wrapping_neg();
+        self.0 ^= bit;
+        Some(bit)
     }
 }
@@ -0,0 +1,418 @@
+use reversi::board::{Bits, Board, Color, Move};
+use reversi::game::Game;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Static square values from the point of view of the disc owner.
+/// Corners are worth the most, the squares next to them are dangerous.
+#[rustfmt::skip]
+pub const SQUARE_WEIGHTS: [i32; 64] = [
+    100, -20,  10,   5,   5,  10, -20, 100,
+    -20, -50,  -2,  -2,  -2,  -2, -50, -20,
+     10,  -2,  -1,  -1,  -1,  -1,  -2,  10,
+      5,  -2,  -1,  -1,  -1,  -1,  -2,   5,
+      5,  -2,  -1,  -1,  -1,  -1,  -2,   5,
+     10,  -2,  -1,  -1,  -1,  -1,  -2,  10,
+    -20, -50,  -2,  -2,  -2,  -2, -50, -20,
+    100, -20,  10,   5,   5,  10, -20, 100,
+];
+
+const CORNERS: u64 = 0x8100_0000_0000_0081;
+/// (corner, X-square diagonally next to it)
+const X_SQUARES: [(u64, u64); 4] = [
+    (1 << 0, 1 << 9),
+    (1 << 7, 1 << 14),
+    (1 << 56, 1 << 49),
+    (1 << 63, 1 << 54),
+];
+
+/// Score of a finished game per disc of difference. Large enough for any
+/// win to outweigh every heuristic evaluation.
+pub const FINAL_SCALE: i32 = 10_000;
+const INFINITY: i32 = i32::MAX;
+pub const MAX_DEPTH: u8 = 60;
+/// How many entries the transposition table may hold before it is cleared.
+const TABLE_SIZE: usize = 1 << 20;
+
+/// Static evaluation of a position.
+pub trait Evaluator {
+    /// Score of `game` from the point of view of the side to move.
+    /// Only called on positions that are not over.
+    fn evaluate(&self, game: &Game) -> i32;
+}
+
+/// Difference in the number of legal moves.
+pub struct Mobility;
+
+impl Evaluator for Mobility {
+    fn evaluate(&self, game: &Game) -> i32 {
+        let own = game.board.legal_moves(game.turn).count_ones() as i32;
+        let opponent = game.board.legal_moves(game.turn.opposite()).count_ones() as i32;
+        own - opponent
+    }
+}
+
+/// Difference in corners held, with a penalty for X-squares next to empty
+/// corners.
+pub struct Corners;
+
+impl Evaluator for Corners {
+    fn evaluate(&self, game: &Game) -> i32 {
+        let own = game.board.discs(game.turn);
+        let opponent = game.board.discs(game.turn.opposite());
+        let mut score =
+            4 * ((own & CORNERS).count_ones() as i32 - (opponent & CORNERS).count_ones() as i32);
+        for &(corner, x) in &X_SQUARES {
+            if game.board.occupied() & corner == 0 {
+                if own & x != 0 {
+                    score -= 2;
+                } else if opponent & x != 0 {
+                    score += 2;
+                }
+            }
+        }
+        score
+    }
+}
+
+/// Difference in the number of discs.
+pub struct DiscParity;
+
+impl Evaluator for DiscParity {
+    fn evaluate(&self, game: &Game) -> i32 {
+        disc_difference(&game.board, game.turn)
+    }
+}
+
+/// Weighted sum of other evaluators.
+pub struct Weighted {
+    terms: Vec<(i32, Box<dyn Evaluator + Send>)>,
+}
+
+impl Weighted {
+    pub fn new() -> Self {
+        Weighted { terms: Vec::new() }
+    }
+
+    pub fn with<E: Evaluator + Send + 'static>(mut self, weight: i32, evaluator: E) -> Self {
+        self.terms.push((weight, Box::new(evaluator)));
+        self
+    }
+
+    /// Mobility and corners first, discs only as a tie-breaker.
+    pub fn standard() -> Self {
+        Weighted::new()
+            .with(10, Mobility)
+            .with(25, Corners)
+            .with(1, DiscParity)
+    }
+}
+
+impl Default for Weighted {
+    fn default() -> Self {
+        Weighted::standard()
+    }
+}
+
+impl Evaluator for Weighted {
+    fn evaluate(&self, game: &Game) -> i32 {
+        self.terms
+            .iter()
+            .map(|(weight, evaluator)| weight * evaluator.evaluate(game))
+            .sum()
+    }
+}
+
+/// How long to search. The search stops at `depth` or when `time` has
+/// elapsed, whichever comes first; depth 1 is always completed.
+#[derive(Clone, Copy, Debug)]
+pub struct Limit {
+    pub depth: u8,
+    pub time: Option<Duration>,
+}
+
+impl Limit {
+    pub fn depth(depth: u8) -> Self {
+        Limit { depth, time: None }
+    }
+
+    pub fn time(time: Duration) -> Self {
+        Limit {
+            depth: MAX_DEPTH,
+            time: Some(time),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    /// `None` if the game is over
+    pub best_move: Option<Move>,
+    /// From the point of view of the side to move
+    pub score: i32,
+    /// Principal variation, starting with `best_move`
+    pub pv: Vec<Move>,
+    /// Deepest completed iteration
+    pub depth: u8,
+    pub nodes: u64,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    depth: u8,
+    score: i32,
+    bound: Bound,
+    best: u64,
+}
+
+/// Negamax alpha-beta searcher with iterative deepening and a
+/// transposition table, which is kept between searches.
+pub struct Engine<E: Evaluator = Weighted> {
+    evaluator: E,
+    table: HashMap<(Board, Color), Entry>,
+    nodes: u64,
+    deadline: Option<Instant>,
+    aborted: bool,
+    /// Depth of the current iteration
+    depth: u8,
+}
+
+impl<E: Evaluator> Engine<E> {
+    pub fn new(evaluator: E) -> Self {
+        Engine {
+            evaluator,
+            table: HashMap::new(),
+            nodes: 0,
+            deadline: None,
+            aborted: false,
+            depth: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.table.clear();
+    }
+
+    pub fn search(&mut self, game: &Game, limit: Limit) -> SearchResult {
+        let mut game = game.clone();
+        self.nodes = 0;
+        self.aborted = false;
+        self.deadline = limit.time.map(|time| Instant::now() + time);
+        if self.table.len() > TABLE_SIZE {
+            self.clear();
+        }
+
+        let mut result = SearchResult {
+            best_move: None,
+            score: 0,
+            pv: Vec::new(),
+            depth: 0,
+            nodes: 0,
+        };
+        for depth in 1..=limit.depth.max(1) {
+            self.depth = depth;
+            let mut pv = Vec::new();
+            let score = self.negamax(&mut game, depth, 0, -INFINITY, INFINITY, &mut pv);
+            if self.aborted {
+                break;
+            }
+            result.best_move = pv.first().cloned();
+            result.score = score;
+            result.pv = pv;
+            result.depth = depth;
+            if game.is_over || score.abs() >= FINAL_SCALE {
+                // the game tree is exhausted, deeper searches change nothing
+                break;
+            }
+            if self.timed_out() {
+                break;
+            }
+        }
+        result.nodes = self.nodes;
+        result
+    }
+
+    fn timed_out(&self) -> bool {
+        self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+
+    /// Fills `pv` with the best line found from `game`, which may be cut
+    /// short by transposition table hits.
+    fn negamax(
+        &mut self,
+        game: &mut Game,
+        depth: u8,
+        ply: u8,
+        mut alpha: i32,
+        beta: i32,
+        pv: &mut Vec<Move>,
+    ) -> i32 {
+        pv.clear();
+        self.nodes += 1;
+        // depth 1 must complete so that there is always a move to return
+        if self.nodes & 1023 == 0 && self.depth > 1 && self.timed_out() {
+            self.aborted = true;
+        }
+        if self.aborted {
+            return 0;
+        }
+        if game.is_over {
+            return disc_difference(&game.board, game.turn) * FINAL_SCALE;
+        }
+        if depth == 0 {
+            return self.evaluator.evaluate(game);
+        }
+
+        let key = (game.board, game.turn);
+        let alpha_orig = alpha;
+        let mut tt_move = 0;
+        if let Some(entry) = self.table.get(&key) {
+            tt_move = entry.best;
+            // only trust the table below the root so that a pv is always found
+            if entry.depth >= depth && ply > 0 {
+                match entry.bound {
+                    Bound::Exact => return entry.score,
+                    Bound::Lower if entry.score >= beta => return entry.score,
+                    Bound::Upper if entry.score <= alpha => return entry.score,
+                    _ => {}
+                }
+            }
+        }
+
+        let turn = game.turn;
+        let mut moves: Vec<u64> = Bits(game.available()).collect();
+        moves.sort_by_key(|&bit| {
+            if bit == tt_move {
+                i32::MIN
+            } else {
+                -SQUARE_WEIGHTS[bit.trailing_zeros() as usize]
+            }
+        });
+
+        let mut best = -INFINITY;
+        let mut best_bit = 0;
+        let mut line = Vec::new();
+        for bit in moves {
+            let p = Board::pos_of(bit);
+            let m = Move {
+                x: p.x,
+                y: p.y,
+                color: turn,
+            };
+            game.play(m).expect("generated move must be legal");
+            // the opponent may have passed, in which case we move again
+            let score = if game.turn == turn {
+                self.negamax(game, depth - 1, ply + 1, alpha, beta, &mut line)
+            } else {
+                -self.negamax(game, depth - 1, ply + 1, -beta, -alpha, &mut line)
+            };
+            game.undo();
+            if self.aborted {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+                best_bit = bit;
+                pv.clear();
+                pv.push(m);
+                pv.extend_from_slice(&line);
+            }
+            if best > alpha {
+                alpha = best;
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best <= alpha_orig {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.insert(
+            key,
+            Entry {
+                depth,
+                score: best,
+                bound,
+                best: best_bit,
+            },
+        );
+        best
+    }
+}
+
+impl Default for Engine<Weighted> {
+    fn default() -> Self {
+        Engine::new(Weighted::standard())
+    }
+}
+
+/// `color`'s discs minus the opponent's.
+pub fn disc_difference(board: &Board, color: Color) -> i32 {
+    board.discs(color).count_ones() as i32 - board.discs(color.opposite()).count_ones() as i32
+}
+
+#[test]
+fn test_alpha_beta_matches_minimax() {
+    fn minimax(game: &mut Game, depth: u8) -> i32 {
+        if game.is_over {
+            return disc_difference(&game.board, game.turn) * FINAL_SCALE;
+        }
+        if depth == 0 {
+            return Weighted::standard().evaluate(game);
+        }
+        let turn = game.turn;
+        let mut best = -INFINITY;
+        for p in game.available_pos() {
+            game.play(Move {
+                x: p.x,
+                y: p.y,
+                color: turn,
+            })
+            .unwrap();
+            let score = if game.turn == turn {
+                minimax(game, depth - 1)
+            } else {
+                -minimax(game, depth - 1)
+            };
+            game.undo();
+            best = best.max(score);
+        }
+        best
+    }
+
+    let mut game = Game::new();
+    let mut engine = Engine::default();
+    for _ in 0..12 {
+        let result = engine.search(&game, Limit::depth(3));
+        assert_eq!(result.score, minimax(&mut game.clone(), 3));
+        assert_eq!(result.pv.len(), 3);
+        let best = result.best_move.unwrap();
+        assert_eq!(result.pv[0], best);
+        game.play(best).unwrap();
+    }
+}
+
+#[test]
+fn test_time_limit() {
+    let mut engine = Engine::default();
+    let start = Instant::now();
+    let result = engine.search(&Game::new(), Limit::time(Duration::from_millis(50)));
+    // stopped by the clock long before the depth limit, with room to spare
+    // for slow unoptimized builds
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(result.best_move.is_some());
+    assert!(result.depth >= 1 && result.depth < MAX_DEPTH);
+}
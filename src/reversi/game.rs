@@ -1,18 +1,54 @@
-use reversi::board::{Board, Cell, Color, Move, Pos};
-
-const DIR: [Pos<i32>; 8] = [
-    Pos { x: 1, y: 0 },
-    Pos { x: 1, y: 1 },
-    Pos { x: 0, y: 1 },
-    Pos { x: -1, y: 1 },
-    Pos { x: -1, y: 0 },
-    Pos { x: -1, y: -1 },
-    Pos { x: 0, y: -1 },
-    Pos { x: 1, y: -1 },
-];
+use reversi::board::{Bits, Board, Cell, Color, Move, Pos};
+use std::error;
+use std::fmt;
 
 pub type Winner = Option<Color>;
 
+/// Why a move was refused.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MoveError {
+    GameOver,
+    /// The move is for the side that is not to move
+    NotYourTurn(Color),
+    /// The square is occupied or flips nothing
+    IllegalSquare(Pos<usize>),
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::GameOver => write!(f, "The game is over"),
+            MoveError::NotYourTurn(color) => write!(f, "It's not {:?} turn", color),
+            MoveError::IllegalSquare(p) => write!(f, "{} is not available", p),
+        }
+    }
+}
+
+impl error::Error for MoveError {}
+
+/// One entry of the game record.
+#[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Ply {
+    Move(Move),
+    Pass(Color),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct State {
+    board: Board,
+    turn: Color,
+    is_over: bool,
+    pass: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Record {
+    pub ply: Ply,
+    /// Discs flipped by the move, 0 for a pass
+    pub flipped: u64,
+    before: State,
+}
+
 #[derive(Clone, Debug)]
 pub struct Game {
     pub board: Board,
@@ -20,6 +56,10 @@ pub struct Game {
     pub is_start: bool,
     pub is_over: bool,
     pub pass: bool,
+    /// Played plies followed by the ones that can be redone
+    history: Vec<Record>,
+    /// Number of plies played, i.e. index of the next redoable record
+    ply: usize,
 }
 
 impl Default for Game {
@@ -31,13 +71,15 @@ impl Default for Game {
         board.set_cell(Pos { x: 4, y: 3 }, Cell::Piece(Color::Black));
 
         let mut game = Self {
-            board: board,
+            board,
             turn: Color::Black,
             is_start: false,
             is_over: false,
             pass: false,
+            history: Vec::new(),
+            ply: 0,
         };
-        game.update_available_cell();
+        game.update_pass();
         game
     }
 }
@@ -47,19 +89,29 @@ impl Game {
         Self::default()
     }
 
+    /// A game starting from an arbitrary position, with no history. The game
+    /// is over if neither side can move; otherwise `turn` should have a legal
+    /// move, since passes are only made after a move.
+    pub fn from_board(board: Board, turn: Color) -> Self {
+        let is_over = !board.has_legal_move(turn) && !board.has_legal_move(turn.opposite());
+        Self {
+            board,
+            turn,
+            is_over,
+            ..Self::default()
+        }
+    }
+
     pub fn change_turn(&mut self) {
-        self.turn = match self.turn {
-            Color::Black => Color::White,
-            _ => Color::Black,
-        };
-        self.update_available_cell();
+        self.turn = self.turn.opposite();
+        self.update_pass();
     }
 
     pub fn winner(&self) -> Winner {
         if !self.is_over {
             return None;
         }
-        let (black, white, _, _) = self.board.count_piece();
+        let (black, white, _) = self.board.count_piece();
         if black > white {
             Some(Color::Black)
         } else if white > black {
@@ -69,103 +121,314 @@ impl Game {
         }
     }
 
-    pub fn put_piece(&mut self, m: Move) -> Result<(), String> {
-        if !Color::equal(&self.turn, &m.color) {
-            return Err(format!("It's not {:?} turn", m.color));
+    /// Squares the side to move can play, as a bitboard.
+    pub fn available(&self) -> u64 {
+        if self.is_over {
+            return 0;
+        }
+        self.board.legal_moves(self.turn)
+    }
+
+    pub fn available_pos(&self) -> Vec<Pos<usize>> {
+        Bits(self.available()).map(Board::pos_of).collect()
+    }
+
+    /// The board with the squares playable by the side to move marked as
+    /// `Cell::Available`.
+    pub fn cells(&self) -> [Cell; 64] {
+        let mut cells = [Cell::Empty; 64];
+        let available = self.available();
+        for (i, cell) in cells.iter_mut().enumerate() {
+            let p = Pos { x: i % 8, y: i / 8 };
+            *cell = if available & Board::bit(p) != 0 {
+                Cell::Available
+            } else {
+                self.board.get_cell(p)
+            };
+        }
+        cells
+    }
+
+    pub fn put_piece(&mut self, m: Move) -> Result<(), MoveError> {
+        if self.is_over {
+            return Err(MoveError::GameOver);
         }
-        if !self.board.get_cell(Pos { x: m.x, y: m.y }).is_available() {
-            return Err("Not Available Cell".to_string());
+        if !Color::equal(&self.turn, &m.color) {
+            return Err(MoveError::NotYourTurn(m.color));
         }
         let pos = Pos { x: m.x, y: m.y };
+        if pos.x >= 8 || pos.y >= 8 || !self.can_put(pos, m.color) {
+            return Err(MoveError::IllegalSquare(pos));
+        }
+        let before = self.state();
         self.board.set_cell(pos, Cell::Piece(m.color));
-        self.flip(pos);
+        let flipped = self.flip(pos);
+        self.record(Ply::Move(m), flipped, before);
         Ok(())
     }
 
-    pub fn update_available_cell(&mut self) {
-        for pos in Board::all_pos()
-            .into_iter()
-            .filter(|&p| !self.board.get_cell(p).is_piece())
-            .collect::<Vec<Pos<usize>>>()
-        {
-            if self.can_put(pos, self.turn) {
-                self.board.set_cell(pos, Cell::Available);
-            } else if self.board.get_cell(pos).is_available() {
-                self.board.set_cell(pos, Cell::Empty);
-            }
-        }
-        let (_, _, available, _) = self.board.count_piece();
-        println!("{}", available);
-        if available == 0 {
-            if self.pass {
-                self.is_over = true;
-            } else {
-                self.pass = true;
-                self.change_turn();
-            }
-        } else {
+    /// `put_piece` followed by `change_turn`.
+    pub fn play(&mut self, m: Move) -> Result<(), MoveError> {
+        self.put_piece(m)?;
+        self.change_turn();
+        Ok(())
+    }
+
+    /// Passes automatically when the side to move has no legal move,
+    /// and ends the game when neither side has one. No pass is recorded
+    /// at the end of the game.
+    pub fn update_pass(&mut self) {
+        if self.board.has_legal_move(self.turn) {
             self.pass = false;
+        } else if !self.board.has_legal_move(self.turn.opposite()) {
+            self.is_over = true;
+        } else {
+            let before = self.state();
+            self.pass = true;
+            self.record(Ply::Pass(self.turn), 0, before);
+            self.change_turn();
         }
     }
 
     pub fn can_put(&self, pos: Pos<usize>, turn: Color) -> bool {
-        for d in &DIR {
-            let mut dir_p = pos + *d;
-            if let Some(p) = dir_p {
-                match self.board.get_cell(p) {
-                    Cell::Piece(color) => {
-                        if Color::equal(&turn, &color) {
-                            continue;
-                        }
-                    }
-                    _ => continue,
-                }
-                dir_p = p + *d;
-            } else {
-                continue;
-            }
-            while let Some(p) = dir_p {
-                if let Cell::Piece(color) = self.board.get_cell(p) {
-                    if Color::equal(&turn, &color) {
-                        return true;
-                    }
-                } else {
-                    break;
-                }
-                dir_p = p + *d;
-            }
+        self.board.flips(turn, Board::bit(pos)) != 0
+    }
+
+    /// Flips the discs bracketed by the disc at `pos` for the side to move.
+    /// Returns the flipped discs as a bitboard.
+    pub fn flip(&mut self, pos: Pos<usize>) -> u64 {
+        let flips = self.board.bracketed(self.turn, Board::bit(pos));
+        self.board.apply(self.turn, 0, flips);
+        flips
+    }
+
+    /// Plies played so far, passes included.
+    pub fn history(&self) -> &[Record] {
+        &self.history[..self.ply]
+    }
+
+    /// Moves played so far, passes excluded.
+    pub fn moves(&self) -> Vec<Move> {
+        self.history()
+            .iter()
+            .filter_map(|r| match r.ply {
+                Ply::Move(m) => Some(m),
+                Ply::Pass(_) => None,
+            })
+            .collect()
+    }
+
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history().iter().any(|r| !r.is_pass())
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.ply < self.history.len()
+    }
+
+    /// Takes back the last move together with the passes it forced.
+    pub fn undo(&mut self) -> Option<Move> {
+        let i = self.history().iter().rposition(|r| !r.is_pass())?;
+        let record = self.history[i];
+        self.restore(record.before);
+        self.ply = i;
+        match record.ply {
+            Ply::Move(m) => Some(m),
+            Ply::Pass(_) => None,
         }
-        false
     }
 
-    pub fn flip(&mut self, pos: Pos<usize>) {
-        for d in &DIR {
-            let p = pos + *d;
-            if p.is_none() {
-                continue;
-            }
-            if let Cell::Piece(color) = self.board.get_cell(p.unwrap()) {
-                if Color::equal(&self.turn, &color) {
-                    continue;
-                }
+    /// Replays the next undone move together with the passes it forces.
+    pub fn redo(&mut self) -> Option<Move> {
+        match self.history.get(self.ply)?.ply {
+            Ply::Move(m) => {
+                self.play(m).ok()?;
+                Some(m)
             }
-            self.flip_recursive(p, *d).ok();
+            Ply::Pass(_) => None,
         }
     }
 
-    pub fn flip_recursive(&mut self, pos: Option<Pos<usize>>, d: Pos<i32>) -> Result<(), String> {
-        if let Some(pos) = pos {
-            if let Cell::Piece(color) = self.board.get_cell(pos) {
-                if Color::equal(&self.turn, &color) {
-                    return Ok(());
-                } else {
-                    if self.flip_recursive(pos + d, d).is_ok() {
-                        self.board.set_cell(pos, Cell::Piece(self.turn));
-                        return Ok(());
-                    }
-                }
-            }
+    /// Moves through the record to ply `n`. Since passes are applied together
+    /// with the move that forced them, the reached ply is returned and may be
+    /// one past `n`.
+    pub fn jump_to(&mut self, n: usize) -> usize {
+        while self.ply > n && self.undo().is_some() {}
+        while self.ply < n && self.redo().is_some() {}
+        self.ply
+    }
+
+    fn state(&self) -> State {
+        State {
+            board: self.board,
+            turn: self.turn,
+            is_over: self.is_over,
+            pass: self.pass,
+        }
+    }
+
+    fn restore(&mut self, state: State) {
+        self.board = state.board;
+        self.turn = state.turn;
+        self.is_over = state.is_over;
+        self.pass = state.pass;
+    }
+
+    /// Appends a ply, discarding the redoable records unless the ply is the
+    /// one that would have been redone.
+    fn record(&mut self, ply: Ply, flipped: u64, before: State) {
+        if self.history.get(self.ply).map(|r| r.ply) != Some(ply) {
+            self.history.truncate(self.ply);
+            self.history.push(Record {
+                ply,
+                flipped,
+                before,
+            });
+        }
+        self.ply += 1;
+    }
+}
+
+impl Record {
+    pub fn is_pass(&self) -> bool {
+        match self.ply {
+            Ply::Move(_) => false,
+            Ply::Pass(_) => true,
         }
-        Err("out of board".to_string())
     }
 }
+
+#[test]
+fn test_perft() {
+    fn perft(game: &Game, depth: u32) -> u64 {
+        if depth == 0 || game.is_over {
+            return 1;
+        }
+        game.available_pos()
+            .into_iter()
+            .map(|p| {
+                let mut next = game.clone();
+                next.put_piece(Move {
+                    x: p.x,
+                    y: p.y,
+                    color: game.turn,
+                })
+                .unwrap();
+                next.change_turn();
+                perft(&next, depth - 1)
+            })
+            .sum()
+    }
+
+    let game = Game::new();
+    let expected = [1, 4, 12, 56, 244, 1396, 8200, 55092];
+    for (depth, &nodes) in expected.iter().enumerate() {
+        assert_eq!(perft(&game, depth as u32), nodes);
+    }
+}
+
+#[test]
+fn test_put_piece() {
+    let mut game = Game::new();
+    assert!(game.cells()[19].is_available());
+    assert_eq!(
+        game.put_piece(Move {
+            x: 0,
+            y: 0,
+            color: Color::Black,
+        }),
+        Err(MoveError::IllegalSquare(Pos { x: 0, y: 0 }))
+    );
+    assert_eq!(
+        game.put_piece(Move {
+            x: 3,
+            y: 2,
+            color: Color::White,
+        }),
+        Err(MoveError::NotYourTurn(Color::White))
+    );
+    game.put_piece(Move {
+        x: 3,
+        y: 2,
+        color: Color::Black,
+    })
+    .unwrap();
+    game.change_turn();
+    assert_eq!(game.board.count_piece(), (4, 1, 59));
+    assert_eq!(game.turn, Color::White);
+    assert_eq!(game.available().count_ones(), 3);
+}
+
+#[test]
+fn test_undo_redo() {
+    let mut game = Game::new();
+    let mut positions = vec![(game.board, game.turn)];
+    while !game.is_over {
+        let p = game.available_pos()[0];
+        game.play(Move {
+            x: p.x,
+            y: p.y,
+            color: game.turn,
+        })
+        .unwrap();
+        positions.push((game.board, game.turn));
+    }
+    let moves = game.moves();
+    assert_eq!(moves.len(), positions.len() - 1);
+
+    for i in (0..moves.len()).rev() {
+        assert_eq!(game.undo(), Some(moves[i]));
+        assert_eq!((game.board, game.turn), positions[i]);
+    }
+    assert!(game.undo().is_none());
+    for i in 0..moves.len() {
+        assert_eq!(game.redo(), Some(moves[i]));
+        assert_eq!((game.board, game.turn), positions[i + 1]);
+    }
+    assert!(game.is_over);
+    assert!(!game.can_redo());
+
+    game.jump_to(10);
+    assert_eq!(game.moves(), &moves[..10]);
+    assert!(game.can_redo());
+    let other = game
+        .available_pos()
+        .into_iter()
+        .find(|&p| p.x != moves[10].x || p.y != moves[10].y)
+        .unwrap();
+    game.play(Move {
+        x: other.x,
+        y: other.y,
+        color: game.turn,
+    })
+    .unwrap();
+    assert!(!game.can_redo());
+}
+
+#[test]
+fn test_undo_pass() {
+    let mut game = Game::new();
+    game.board = Board::from_bits(1 | 1 << 16, 1 << 1 | 1 << 17);
+    game.play(Move {
+        x: 2,
+        y: 0,
+        color: Color::Black,
+    })
+    .unwrap();
+    assert_eq!(game.turn, Color::Black);
+    assert_eq!(game.ply(), 2);
+    assert_eq!(game.history()[0].flipped, 1 << 1);
+    assert_eq!(game.history()[1].ply, Ply::Pass(Color::White));
+
+    game.undo();
+    assert_eq!(game.ply(), 0);
+    assert_eq!(game.turn, Color::Black);
+    assert_eq!(game.board, Board::from_bits(1 | 1 << 16, 1 << 1 | 1 << 17));
+    game.redo();
+    assert_eq!(game.ply(), 2);
+    assert!(!game.can_redo());
+}
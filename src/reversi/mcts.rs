@@ -0,0 +1,217 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use reversi::board::{Bits, Board, Color, Move};
+use reversi::game::Game;
+use std::f64::consts::SQRT_2;
+use std::time::{Duration, Instant};
+
+/// How much work a search may do.
+#[derive(Clone, Copy, Debug)]
+pub enum Budget {
+    Iterations(u32),
+    Time(Duration),
+}
+
+#[derive(Clone, Debug)]
+pub struct MctsResult {
+    /// `None` if the game is over
+    pub best_move: Option<Move>,
+    /// Visits of the chosen move
+    pub visits: u32,
+    /// Share of the playouts through the chosen move won by the side to move
+    pub win_rate: f64,
+    pub iterations: u32,
+}
+
+struct Node {
+    parent: Option<usize>,
+    /// Move leading to this node, `None` for the root
+    reversi_move: Option<Move>,
+    children: Vec<usize>,
+    untried: Vec<Move>,
+    visits: u32,
+    /// Playouts won by the player who made `reversi_move`, draws count half
+    wins: f64,
+}
+
+impl Node {
+    fn new(parent: Option<usize>, reversi_move: Option<Move>, game: &Game) -> Self {
+        let untried = game
+            .available_pos()
+            .into_iter()
+            .map(|p| Move {
+                x: p.x,
+                y: p.y,
+                color: game.turn,
+            })
+            .collect();
+        Node {
+            parent,
+            reversi_move,
+            children: Vec::new(),
+            untried,
+            visits: 0,
+            wins: 0.0,
+        }
+    }
+}
+
+/// Monte Carlo Tree Search player using UCT selection and uniformly random
+/// playouts. Needs no evaluation function.
+pub struct Mcts {
+    budget: Budget,
+    exploration: f64,
+    rng: StdRng,
+}
+
+impl Mcts {
+    /// The same seed and iteration budget always give the same moves.
+    pub fn new(budget: Budget, seed: u64) -> Self {
+        Mcts {
+            budget,
+            exploration: SQRT_2,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Sets the UCT exploration constant, √2 by default.
+    pub fn with_exploration(mut self, exploration: f64) -> Self {
+        self.exploration = exploration;
+        self
+    }
+
+    pub fn choose_move(&mut self, game: &Game) -> Option<Move> {
+        self.search(game).best_move
+    }
+
+    pub fn search(&mut self, game: &Game) -> MctsResult {
+        let mut nodes = vec![Node::new(None, None, game)];
+        let start = Instant::now();
+        let mut iterations = 0;
+        loop {
+            let done = match self.budget {
+                Budget::Iterations(n) => iterations >= n,
+                Budget::Time(time) => iterations > 0 && start.elapsed() >= time,
+            };
+            if done || game.is_over {
+                break;
+            }
+            self.iterate(&mut nodes, game.clone());
+            iterations += 1;
+        }
+
+        let best = nodes[0]
+            .children
+            .iter()
+            .cloned()
+            .max_by_key(|&child| nodes[child].visits);
+        match best {
+            Some(child) => {
+                let node = &nodes[child];
+                MctsResult {
+                    best_move: node.reversi_move,
+                    visits: node.visits,
+                    win_rate: node.wins / f64::from(node.visits),
+                    iterations,
+                }
+            }
+            None => MctsResult {
+                best_move: None,
+                visits: 0,
+                win_rate: 0.0,
+                iterations,
+            },
+        }
+    }
+
+    fn iterate(&mut self, nodes: &mut Vec<Node>, mut game: Game) {
+        // selection
+        let mut current = 0;
+        while nodes[current].untried.is_empty() && !nodes[current].children.is_empty() {
+            current = self.select(nodes, current);
+            let m = nodes[current].reversi_move.unwrap();
+            game.play(m).expect("tree move must be legal");
+        }
+
+        // expansion
+        if !nodes[current].untried.is_empty() {
+            let i = self.rng.gen_range(0, nodes[current].untried.len());
+            let m = nodes[current].untried.swap_remove(i);
+            game.play(m).expect("untried move must be legal");
+            nodes.push(Node::new(Some(current), Some(m), &game));
+            let child = nodes.len() - 1;
+            nodes[current].children.push(child);
+            current = child;
+        }
+
+        // simulation
+        while !game.is_over {
+            let available: Vec<u64> = Bits(game.available()).collect();
+            let bit = available[self.rng.gen_range(0, available.len())];
+            let p = Board::pos_of(bit);
+            let turn = game.turn;
+            game.play(Move {
+                x: p.x,
+                y: p.y,
+                color: turn,
+            })
+            .expect("available move must be legal");
+        }
+
+        // backpropagation
+        let winner = game.winner();
+        let mut node = Some(current);
+        while let Some(i) = node {
+            let n = &mut nodes[i];
+            n.visits += 1;
+            if let Some(m) = n.reversi_move {
+                n.wins += reward(winner, m.color);
+            }
+            node = n.parent;
+        }
+    }
+
+    /// Child of `parent` with the highest UCT value.
+    fn select(&self, nodes: &[Node], parent: usize) -> usize {
+        let ln_visits = f64::from(nodes[parent].visits).ln();
+        let uct = |child: usize| {
+            let n = &nodes[child];
+            let visits = f64::from(n.visits);
+            n.wins / visits + self.exploration * (ln_visits / visits).sqrt()
+        };
+        let mut best = nodes[parent].children[0];
+        for &child in &nodes[parent].children[1..] {
+            if uct(child) > uct(best) {
+                best = child;
+            }
+        }
+        best
+    }
+}
+
+fn reward(winner: Option<Color>, player: Color) -> f64 {
+    match winner {
+        Some(color) if color == player => 1.0,
+        Some(_) => 0.0,
+        None => 0.5,
+    }
+}
+
+#[test]
+fn test_seeded_search_is_reproducible() {
+    let game = Game::new();
+    let first = Mcts::new(Budget::Iterations(200), 42).search(&game);
+    let second = Mcts::new(Budget::Iterations(200), 42).search(&game);
+    assert_eq!(first.iterations, 200);
+    assert_eq!(first.best_move, second.best_move);
+    assert_eq!(first.visits, second.visits);
+
+    let m = first.best_move.unwrap();
+    assert!(game.can_put(::reversi::board::Pos { x: m.x, y: m.y }, m.color));
+
+    let mut over = Game::new();
+    over.is_over = true;
+    assert!(Mcts::new(Budget::Time(Duration::from_millis(10)), 0)
+        .choose_move(&over)
+        .is_none());
+}
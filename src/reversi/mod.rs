@@ -1,2 +1,8 @@
+pub mod ai;
 pub mod board;
+pub mod engine;
 pub mod game;
+pub mod mcts;
+pub mod notation;
+pub mod position;
+pub mod solver;
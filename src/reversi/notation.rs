@@ -0,0 +1,242 @@
+use reversi::board::{Color, Move, Pos};
+use reversi::game::{Game, Ply};
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+const PASS: &str = "pa";
+
+/// Squares are written `a1` to `h8`: the letter is the column (`x`) and the
+/// number the row (`y + 1`), both counted from the top left corner.
+impl fmt::Display for Pos<usize> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", (b'a' + self.x as u8) as char, self.y + 1)
+    }
+}
+
+impl FromStr for Pos<usize> {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some(col), Some(row), None) => {
+                let x = "abcdefgh".find(col.to_ascii_lowercase());
+                let y = "12345678".find(row);
+                match (x, y) {
+                    (Some(x), Some(y)) => Ok(Pos { x, y }),
+                    _ => Err(NotationError(s.to_owned())),
+                }
+            }
+            _ => Err(NotationError(s.to_owned())),
+        }
+    }
+}
+
+/// Black moves are written in upper case (`F5`), white moves in lower case
+/// (`d6`).
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = Pos {
+            x: self.x,
+            y: self.y,
+        }
+        .to_string();
+        match self.color {
+            Color::Black => write!(f, "{}", s.to_uppercase()),
+            Color::White => write!(f, "{}", s),
+        }
+    }
+}
+
+impl FromStr for Move {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let p: Pos<usize> = s.parse()?;
+        let color = if s.starts_with(|c: char| c.is_ascii_uppercase()) {
+            Color::Black
+        } else {
+            Color::White
+        };
+        Ok(Move {
+            x: p.x,
+            y: p.y,
+            color,
+        })
+    }
+}
+
+/// A square that is not in `a1`-`h8` notation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NotationError(pub String);
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid square {:?}", self.0)
+    }
+}
+
+impl error::Error for NotationError {}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TranscriptErrorKind {
+    Syntax(String),
+    IllegalMove(Pos<usize>),
+    /// A pass where the side to move had a legal move
+    UnexpectedPass,
+    /// A move after the end of the game
+    GameOver,
+}
+
+/// Why a transcript could not be replayed, with the index of the offending
+/// ply in the transcript, counted from 0.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TranscriptError {
+    pub ply: usize,
+    pub kind: TranscriptErrorKind,
+}
+
+impl fmt::Display for TranscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            TranscriptErrorKind::Syntax(ref s) => {
+                write!(f, "ply {}: cannot read {:?}", self.ply, s)
+            }
+            TranscriptErrorKind::IllegalMove(p) => write!(f, "ply {}: {} is illegal", self.ply, p),
+            TranscriptErrorKind::UnexpectedPass => {
+                write!(f, "ply {}: pass while a move is available", self.ply)
+            }
+            TranscriptErrorKind::GameOver => {
+                write!(f, "ply {}: the game is already over", self.ply)
+            }
+        }
+    }
+}
+
+impl error::Error for TranscriptError {}
+
+/// Transcripts are the moves of a game run together, like `f5d6c3d3c4`.
+/// Case and whitespace are ignored. Passes are written `pa` and are optional
+/// when reading, since they are forced.
+impl Game {
+    pub fn to_transcript(&self) -> String {
+        self.history()
+            .iter()
+            .map(|r| match r.ply {
+                Ply::Move(m) => Pos { x: m.x, y: m.y }.to_string(),
+                Ply::Pass(_) => PASS.to_owned(),
+            })
+            .collect()
+    }
+
+    pub fn from_transcript(s: &str) -> Result<Game, TranscriptError> {
+        let chars: Vec<char> = s.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut game = Game::new();
+        // forced passes that the transcript may still spell out
+        let mut passes = 0;
+        for (ply, token) in chars.chunks(2).enumerate() {
+            let token: String = token.iter().collect::<String>().to_lowercase();
+            let error = |kind| TranscriptError { ply, kind };
+            if token == PASS || token == "ps" || token == "--" {
+                // older transcripts end with a pass for the side that
+                // could not move
+                if game.is_over {
+                    continue;
+                }
+                if passes == 0 {
+                    return Err(error(TranscriptErrorKind::UnexpectedPass));
+                }
+                passes -= 1;
+                continue;
+            }
+            let p: Pos<usize> = token
+                .parse()
+                .map_err(|_| error(TranscriptErrorKind::Syntax(token.clone())))?;
+            if game.is_over {
+                return Err(error(TranscriptErrorKind::GameOver));
+            }
+            let before = game.ply();
+            let turn = game.turn;
+            game.play(Move {
+                x: p.x,
+                y: p.y,
+                color: turn,
+            })
+            .map_err(|_| error(TranscriptErrorKind::IllegalMove(p)))?;
+            passes = game.ply() - before - 1;
+        }
+        Ok(game)
+    }
+}
+
+#[test]
+fn test_square_notation() {
+    assert_eq!(Pos { x: 5, y: 4 }.to_string(), "f5");
+    assert_eq!("F5".parse::<Pos<usize>>().unwrap().x, 5);
+    assert_eq!("a8".parse::<Pos<usize>>().unwrap().y, 7);
+    assert!("i1".parse::<Pos<usize>>().is_err());
+    assert!("a9".parse::<Pos<usize>>().is_err());
+    assert!("a10".parse::<Pos<usize>>().is_err());
+
+    let m: Move = "F5".parse().unwrap();
+    assert_eq!(m.color, Color::Black);
+    assert_eq!(m.to_string(), "F5");
+    assert_eq!("d6".parse::<Move>().unwrap().color, Color::White);
+}
+
+#[test]
+fn test_transcript() {
+    let game = Game::from_transcript("f5d6 C3D3c4").unwrap();
+    assert_eq!(game.moves().len(), 5);
+    assert_eq!(game.to_transcript(), "f5d6c3d3c4");
+
+    // replaying a whole game, passes included, gives the same transcript
+    let mut game = Game::new();
+    while !game.is_over {
+        let p = *game.available_pos().last().unwrap();
+        let turn = game.turn;
+        game.play(Move {
+            x: p.x,
+            y: p.y,
+            color: turn,
+        })
+        .unwrap();
+    }
+    let transcript = game.to_transcript();
+    // the end of the game is not a pass
+    assert!(!transcript.ends_with(PASS));
+    let replayed = Game::from_transcript(&transcript).unwrap();
+    assert_eq!(replayed.board, game.board);
+    assert!(replayed.is_over);
+    let with_trailing_pass = format!("{}{}", transcript, PASS);
+    assert_eq!(
+        Game::from_transcript(&with_trailing_pass)
+            .unwrap()
+            .to_transcript(),
+        transcript
+    );
+    let without_passes = transcript.replace(PASS, "");
+    assert_eq!(
+        Game::from_transcript(&without_passes)
+            .unwrap()
+            .to_transcript(),
+        transcript
+    );
+
+    assert_eq!(
+        Game::from_transcript("f5f5").unwrap_err(),
+        TranscriptError {
+            ply: 1,
+            kind: TranscriptErrorKind::IllegalMove(Pos { x: 5, y: 4 }),
+        }
+    );
+    assert_eq!(
+        Game::from_transcript("f5pa").unwrap_err().kind,
+        TranscriptErrorKind::UnexpectedPass
+    );
+    assert_eq!(
+        Game::from_transcript("f5z9").unwrap_err().kind,
+        TranscriptErrorKind::Syntax("z9".to_owned())
+    );
+}
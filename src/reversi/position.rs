@@ -0,0 +1,162 @@
+use reversi::board::{Board, Cell, Color, Pos};
+use reversi::game::Game;
+use std::error;
+use std::fmt;
+
+/// Why a position string could not be parsed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PositionError {
+    /// The board part does not have 64 squares
+    Length(usize),
+    InvalidSquare {
+        index: usize,
+        found: char,
+    },
+    MissingSide,
+    InvalidSide(char),
+    /// The side to move has no legal move but the game is not over
+    MustPass,
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PositionError::Length(n) => write!(f, "expected 64 squares, found {}", n),
+            PositionError::InvalidSquare { index, found } => {
+                write!(f, "invalid square {:?} at index {}", found, index)
+            }
+            PositionError::MissingSide => write!(f, "side to move is missing"),
+            PositionError::InvalidSide(s) => write!(f, "invalid side to move {:?}", s),
+            PositionError::MustPass => write!(f, "the side to move has to pass"),
+        }
+    }
+}
+
+impl error::Error for PositionError {}
+
+/// Position strings are the 64 squares from a1 to h8, row by row, as `X`
+/// (black), `O` (white) or `-` (empty), followed by the side to move:
+///
+/// ```text
+/// ---------------------------OX------XO--------------------------- X
+/// ```
+///
+/// `*` is also read as black and `.` as empty, and whitespace inside the
+/// board part is ignored.
+impl Game {
+    pub fn to_position_string(&self) -> String {
+        let mut s: String = Board::all_pos()
+            .into_iter()
+            .map(|p| match self.board.get_cell(p) {
+                Cell::Piece(Color::Black) => 'X',
+                Cell::Piece(Color::White) => 'O',
+                _ => '-',
+            })
+            .collect();
+        s.push(' ');
+        s.push(side_char(self.turn));
+        s
+    }
+
+    pub fn from_position_string(s: &str) -> Result<Game, PositionError> {
+        let chars: Vec<char> = s.chars().filter(|c| !c.is_whitespace()).collect();
+        match chars.len() {
+            n if n < 64 => return Err(PositionError::Length(n)),
+            64 => return Err(PositionError::MissingSide),
+            65 => {}
+            n => return Err(PositionError::Length(n - 1)),
+        }
+
+        let mut board = Board::new();
+        for (index, &c) in chars[..64].iter().enumerate() {
+            let cell = match c {
+                'X' | 'x' | '*' => Cell::Piece(Color::Black),
+                'O' | 'o' => Cell::Piece(Color::White),
+                '-' | '.' => Cell::Empty,
+                found => return Err(PositionError::InvalidSquare { index, found }),
+            };
+            board.set_cell(
+                Pos {
+                    x: index % 8,
+                    y: index / 8,
+                },
+                cell,
+            );
+        }
+
+        let turn = match chars[64] {
+            'X' | 'x' | '*' => Color::Black,
+            'O' | 'o' => Color::White,
+            c => return Err(PositionError::InvalidSide(c)),
+        };
+        let game = Game::from_board(board, turn);
+        if !game.is_over && game.available() == 0 {
+            return Err(PositionError::MustPass);
+        }
+        Ok(game)
+    }
+}
+
+fn side_char(color: Color) -> char {
+    match color {
+        Color::Black => 'X',
+        Color::White => 'O',
+    }
+}
+
+#[test]
+fn test_position_string() {
+    let start = "---------------------------OX------XO--------------------------- X";
+    assert_eq!(Game::new().to_position_string(), start);
+
+    let game = Game::from_position_string(start).unwrap();
+    assert_eq!(game.board, Game::new().board);
+    assert_eq!(game.turn, Color::Black);
+
+    let mut game = Game::new();
+    let p = game.available_pos()[0];
+    game.play(::reversi::board::Move {
+        x: p.x,
+        y: p.y,
+        color: Color::Black,
+    })
+    .unwrap();
+    let parsed = Game::from_position_string(&game.to_position_string()).unwrap();
+    assert_eq!(parsed.board, game.board);
+    assert_eq!(parsed.turn, Color::White);
+
+    // white has no move here, black does
+    let must_pass = format!("XO{} O", "-".repeat(62));
+    assert_eq!(
+        Game::from_position_string(&must_pass).unwrap_err(),
+        PositionError::MustPass
+    );
+    let game = Game::from_position_string(&must_pass.replace(" O", " X")).unwrap();
+    assert_eq!(game.turn, Color::Black);
+    assert!(game.history().is_empty());
+    // neither side can move: the game is over and keeps its side to move
+    let over = format!("XX{} O", "-".repeat(62));
+    let game = Game::from_position_string(&over).unwrap();
+    assert!(game.is_over);
+    assert_eq!(game.to_position_string(), over);
+
+    assert_eq!(
+        Game::from_position_string("XO-").unwrap_err(),
+        PositionError::Length(3)
+    );
+    assert_eq!(
+        Game::from_position_string(&start[..64]).unwrap_err(),
+        PositionError::MissingSide
+    );
+    assert_eq!(
+        Game::from_position_string(&start.replace(" X", " W")).unwrap_err(),
+        PositionError::InvalidSide('W')
+    );
+    assert_eq!(
+        Game::from_position_string(&start.replacen('-', "#", 1)).unwrap_err(),
+        PositionError::InvalidSquare {
+            index: 0,
+            found: '#',
+        }
+    );
+}
@@ -0,0 +1,194 @@
+use reversi::board::{Bits, Board, Color, Move};
+use reversi::engine::disc_difference;
+use reversi::game::{Game, Ply};
+
+/// Positions with more empty squares than this take too long to solve.
+pub const MAX_EMPTIES: u32 = 20;
+
+/// Quadrants of the board, used for parity ordering.
+const QUADRANTS: [u64; 4] = [
+    0x0000_0000_0f0f_0f0f,
+    0x0000_0000_f0f0_f0f0,
+    0x0f0f_0f0f_0000_0000,
+    0xf0f0_f0f0_0000_0000,
+];
+
+#[derive(Clone, Debug)]
+pub struct Solution {
+    /// Final disc difference from the point of view of the side to move,
+    /// with empty squares going to the winner
+    pub score: i32,
+    /// Optimal line to the end of the game, passes included
+    pub line: Vec<Ply>,
+    pub nodes: u64,
+}
+
+/// Exact endgame solver. Works on the bitboards directly since it visits
+/// far more positions than the heuristic `engine`.
+#[derive(Default)]
+pub struct Solver {
+    nodes: u64,
+}
+
+impl Solver {
+    pub fn new() -> Self {
+        Solver::default()
+    }
+
+    /// Solves `game` to the end, or returns `None` if it has more than
+    /// `MAX_EMPTIES` empty squares.
+    pub fn solve(&mut self, game: &Game) -> Option<Solution> {
+        let empties = game.board.empties().count_ones();
+        if empties > MAX_EMPTIES {
+            return None;
+        }
+        self.nodes = 0;
+        let mut line = Vec::new();
+        let score = if game.is_over {
+            final_score(&game.board, game.turn)
+        } else {
+            self.search(game.board, game.turn, -64, 64, &mut line)
+        };
+        Some(Solution {
+            score,
+            line,
+            nodes: self.nodes,
+        })
+    }
+
+    /// Negamax over (board, side to move), filling `line` with the best
+    /// continuation.
+    fn search(
+        &mut self,
+        board: Board,
+        turn: Color,
+        mut alpha: i32,
+        beta: i32,
+        line: &mut Vec<Ply>,
+    ) -> i32 {
+        line.clear();
+        self.nodes += 1;
+
+        let moves = board.legal_moves(turn);
+        if moves == 0 {
+            if !board.has_legal_move(turn.opposite()) {
+                return final_score(&board, turn);
+            }
+            let mut rest = Vec::new();
+            let score = -self.search(board, turn.opposite(), -beta, -alpha, &mut rest);
+            line.push(Ply::Pass(turn));
+            line.extend(rest);
+            return score;
+        }
+
+        let mut best = -65;
+        let mut rest = Vec::new();
+        for bit in order(&board, turn, moves) {
+            let mut next = board;
+            next.apply(turn, bit, board.flips(turn, bit));
+            let score = -self.search(next, turn.opposite(), -beta, -alpha, &mut rest);
+            if score > best {
+                best = score;
+                let p = Board::pos_of(bit);
+                line.clear();
+                line.push(Ply::Move(Move {
+                    x: p.x,
+                    y: p.y,
+                    color: turn,
+                }));
+                line.extend_from_slice(&rest);
+            }
+            if best > alpha {
+                alpha = best;
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+}
+
+/// Moves in odd quadrants first (parity), then fastest-first: the moves
+/// leaving the opponent the fewest replies.
+fn order(board: &Board, turn: Color, moves: u64) -> Vec<u64> {
+    let empties = board.empties();
+    let odd = QUADRANTS
+        .iter()
+        .filter(|&&q| (q & empties).count_ones() % 2 == 1)
+        .fold(0, |acc, q| acc | q);
+    let mut ordered: Vec<(u32, u64)> = Bits(moves)
+        .map(|bit| {
+            let mut next = *board;
+            next.apply(turn, bit, board.flips(turn, bit));
+            let replies = next.legal_moves(turn.opposite()).count_ones();
+            let parity = if odd & bit != 0 { 0 } else { 1 };
+            (parity * 64 + replies, bit)
+        })
+        .collect();
+    ordered.sort_by_key(|&(key, _)| key);
+    ordered.into_iter().map(|(_, bit)| bit).collect()
+}
+
+/// Disc difference at the end of the game, with the empty squares counted
+/// for the winner.
+pub fn final_score(board: &Board, turn: Color) -> i32 {
+    let diff = disc_difference(board, turn);
+    let empties = board.empties().count_ones() as i32;
+    if diff > 0 {
+        diff + empties
+    } else if diff < 0 {
+        diff - empties
+    } else {
+        0
+    }
+}
+
+#[test]
+fn test_solve_matches_game() {
+    use reversi::engine::{Engine, Limit};
+
+    // play a deterministic game down to 10 empties
+    let mut game = Game::new();
+    let mut engine = Engine::default();
+    while game.board.empties().count_ones() > 10 {
+        let m = engine.search(&game, Limit::depth(1)).best_move.unwrap();
+        game.play(m).unwrap();
+    }
+
+    let solution = Solver::new().solve(&game).unwrap();
+    let turn = game.turn;
+    for ply in &solution.line {
+        match *ply {
+            Ply::Move(m) => game.play(m).unwrap(),
+            Ply::Pass(color) => assert_eq!(color, game.turn.opposite()),
+        }
+    }
+    assert!(game.is_over);
+    assert_eq!(final_score(&game.board, turn), solution.score);
+    let expected = match solution.score {
+        s if s > 0 => Some(turn),
+        s if s < 0 => Some(turn.opposite()),
+        _ => None,
+    };
+    assert_eq!(game.winner(), expected);
+}
+
+#[test]
+fn test_solve_wipeout() {
+    // a1 takes white's last discs and leaves a8 empty, which goes to black
+    let mut game = Game::new();
+    game.board = Board::from_bits(!0b111 & !(1 << 56), 0b110);
+    let solution = Solver::new().solve(&game).unwrap();
+    assert_eq!(solution.score, 64);
+    assert_eq!(solution.line.len(), 1);
+    assert_eq!(
+        solution.line[0],
+        Ply::Move(Move {
+            x: 0,
+            y: 0,
+            color: Color::Black,
+        })
+    );
+    assert!(Solver::new().solve(&Game::new()).is_none());
+}
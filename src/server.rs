@@ -1,60 +1,343 @@
+use account::{Account, Credentials};
 use actix::prelude::*;
+use clock::{Clock, ClockTimes, TimeControl};
 use message::*;
+use protocol::RequestId;
 use rand::{self, rngs::ThreadRng, Rng};
-use reversi::board::{Color, Move as ReversiMove};
-use reversi::game::{Game as ReversiGame, Winner};
-use std::collections::{HashMap, HashSet};
+use rating::{self, Rating};
+use reversi::ai::{Analysis, Level};
+use reversi::board::{Color, Move as ReversiMove, Pos};
+use reversi::game::{Game as ReversiGame, MoveError, Winner};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
+use storage::{self, ArchivedGame, MemoryArchive, Storage, StorageError, Termination, Timestamp};
+use worker::{
+    self, AnalyzePosition, ChooseMove, EngineWorker, HashPassword, PasswordWorker, VerifyPassword,
+};
 
 type Uid = usize;
 type Uname = String;
+type Token = String;
+
+/// How long a disconnected player's seat is held for `/resume`
+pub const RESUME_GRACE: Duration = Duration::from_secs(60);
+/// Rooms open at the same time
+pub const MAX_ROOMS: usize = 1000;
+/// Players listed on the leaderboard
+pub const LEADERBOARD_SIZE: usize = 20;
+/// Longest chat message, in characters
+pub const MAX_CHAT_LEN: usize = 500;
+/// Page invite links open
+pub const INVITE_PAGE: &str = "/html/reversi.html";
+/// Chat messages kept for those who come in later
+pub const CHAT_SCROLLBACK: usize = 20;
+/// Analyses running or waiting for a thread at the same time
+pub const MAX_PENDING_ANALYSES: usize = 4;
+/// How long a session token from `/login` can be used
+pub const SESSION_LIFETIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
 
-#[derive(Message)]
 pub struct MakeRoom {
     pub name: String,
     pub uid: Uid,
     pub uname: Uname,
     pub color: Option<Color>,
+    /// Seat a computer player of this level as the opponent
+    pub opponent: Option<Level>,
+    pub time_control: Option<TimeControl>,
+    /// Hide the room from the room list
+    pub private: bool,
+    pub password: Option<String>,
+}
+
+impl actix::Message for MakeRoom {
+    type Result = Result<(), ReversiError>;
+}
+
+/// Where a chat message goes
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ChatChannel {
+    Lobby,
+    Room(String),
+}
+
+pub struct Chat {
+    pub uid: Uid,
+    pub channel: ChatChannel,
+    pub text: String,
+}
+
+impl actix::Message for Chat {
+    type Result = Result<(), ReversiError>;
+}
+
+/// Ask for a one-time code that lets someone into the room
+pub struct Invite {
+    pub uid: Uid,
+    pub room: String,
+}
+
+impl actix::Message for Invite {
+    type Result = Result<String, ReversiError>;
+}
+
+/// Wait for an opponent with the same time control
+pub struct Queue {
+    pub uid: Uid,
+    pub uname: Uname,
+    pub time_control: Option<TimeControl>,
+    /// Only accept opponents rated within this many points
+    pub band: Option<u32>,
+}
+
+impl actix::Message for Queue {
+    type Result = Result<(), ReversiError>;
+}
+
+pub struct Unqueue {
+    pub uid: Uid,
+}
+
+impl actix::Message for Unqueue {
+    type Result = Result<(), ReversiError>;
 }
 
-#[derive(Message)]
 pub struct ClientReversiMoveMessage {
     pub id: usize,
     pub reversi_move: ReversiMove,
     pub room: String,
 }
 
-#[derive(Message)]
+impl actix::Message for ClientReversiMoveMessage {
+    type Result = Result<(), ReversiError>;
+}
+
+/// A move the engine worker chose for a computer player, played only if the
+/// room is still at `generation`
+struct ComputerMove {
+    id: Uid,
+    room: String,
+    generation: u64,
+    reversi_move: ReversiMove,
+}
+
+impl actix::Message for ComputerMove {
+    type Result = ();
+}
+
+/// What a player can do in a game besides moving
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    Abort,
+    /// Ask to take back one's last move
+    RequestTakeback,
+    AcceptTakeback,
+    DeclineTakeback,
+    /// Ask for another game in the same room, with colors swapped
+    Rematch,
+}
+
+pub struct PlayerAction {
+    pub id: Uid,
+    pub room: String,
+    pub action: Action,
+}
+
+impl actix::Message for PlayerAction {
+    type Result = Result<(), ReversiError>;
+}
+
+#[derive(Serialize, Deserialize, Message, Clone, Debug, Eq, PartialEq)]
 pub enum ReversiError {
-    InvalidMove,
+    /// The request could not be read
+    InvalidRequest(String),
+    NoSuchRoom(String),
+    RoomExists(String),
+    RoomFull(String),
+    /// Joining a room one already plays in
+    AlreadySeated,
+    GameNotStarted,
+    GameOver,
+    /// Spectators cannot move
+    NotAPlayer,
+    NotYourTurn,
+    IllegalSquare {
+        x: usize,
+        y: usize,
+    },
+    InvalidResumeToken,
+    /// Accepting a draw nobody offered
+    NoDrawOffer,
+    /// Aborting after the first move
+    CannotAbort,
+    /// Asking for a takeback before having moved
+    NoMoveToTakeBack,
+    /// Answering a takeback nobody asked for
+    NoTakebackRequest,
+    /// Asking for a rematch before the game is over
+    GameNotOver,
+    OpponentLeft,
+    NotQueued,
+    /// Registering a taken name, or using a registered one as a guest
+    NameTaken(String),
+    InvalidCredentials,
+    InvalidSessionToken,
+    /// Reading or writing the archive failed
+    Storage,
+    /// Too many analyses are already running
+    Busy,
+    TooManyRooms,
+    /// Entering a private or password-protected room without a valid
+    /// password or invite code
+    RoomLocked(String),
+    /// Chatting in a room one is neither playing nor watching
+    NotInRoom,
+    ChatTooLong,
+}
+
+impl fmt::Display for ReversiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReversiError::InvalidRequest(s) => write!(f, "{}", s),
+            ReversiError::NoSuchRoom(room) => write!(f, "Room {} does not exist", room),
+            ReversiError::RoomExists(room) => write!(f, "Room {} is already created", room),
+            ReversiError::RoomFull(room) => write!(f, "Room {} is full", room),
+            ReversiError::AlreadySeated => write!(f, "You are already in this room"),
+            ReversiError::GameNotStarted => write!(f, "Game is not started"),
+            ReversiError::GameOver => write!(f, "Game is over"),
+            ReversiError::NotAPlayer => write!(f, "Spectators cannot move"),
+            ReversiError::NotYourTurn => write!(f, "It's not your turn"),
+            ReversiError::IllegalSquare { x, y } => {
+                write!(f, "{} is not available", Pos { x: *x, y: *y })
+            }
+            ReversiError::InvalidResumeToken => write!(f, "Invalid resume token"),
+            ReversiError::NoDrawOffer => write!(f, "Your opponent has not offered a draw"),
+            ReversiError::CannotAbort => {
+                write!(f, "Game can only be aborted before the first move")
+            }
+            ReversiError::NoMoveToTakeBack => write!(f, "You have no move to take back"),
+            ReversiError::NoTakebackRequest => {
+                write!(f, "Your opponent has not asked for a takeback")
+            }
+            ReversiError::GameNotOver => write!(f, "Game is still in progress"),
+            ReversiError::OpponentLeft => write!(f, "Your opponent has left"),
+            ReversiError::NotQueued => write!(f, "You are not in the queue"),
+            ReversiError::NameTaken(name) => write!(f, "Name {} is already registered", name),
+            ReversiError::InvalidCredentials => write!(f, "Wrong name or password"),
+            ReversiError::InvalidSessionToken => write!(f, "Invalid session token"),
+            ReversiError::Storage => write!(f, "Something went wrong, please try again later"),
+            ReversiError::Busy => write!(f, "The server is busy, please try again later"),
+            ReversiError::TooManyRooms => write!(f, "No more rooms can be made right now"),
+            ReversiError::RoomLocked(room) => {
+                write!(f, "Room {} needs a password or an invite code", room)
+            }
+            ReversiError::NotInRoom => write!(f, "You are not in this room"),
+            ReversiError::ChatTooLong => write!(
+                f,
+                "Chat messages are limited to {} characters",
+                MAX_CHAT_LEN
+            ),
+        }
+    }
+}
+
+impl From<MoveError> for ReversiError {
+    fn from(e: MoveError) -> Self {
+        match e {
+            MoveError::GameOver => ReversiError::GameOver,
+            MoveError::NotYourTurn(_) => ReversiError::NotYourTurn,
+            MoveError::IllegalSquare(p) => ReversiError::IllegalSquare { x: p.x, y: p.y },
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Message, Clone)]
 pub struct Game {
     board: Vec<String>,
     turn: Color,
+    /// Only for timed games
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    clock: Option<ClockTimes>,
 }
 
 impl From<&ReversiGame> for Game {
     fn from(game: &ReversiGame) -> Self {
-        let board = game
-            .board
-            .iter()
-            .cloned()
-            .map(|cell| cell.to_string())
-            .collect();
+        let board = game.cells().iter().map(|cell| cell.to_string()).collect();
         Self {
-            board: board,
+            board,
             turn: game.turn,
+            clock: None,
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Message, Clone)]
 pub struct ReversiMessage {
+    /// Id of the request this replies to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<RequestId>,
     kind: ReversiMessageKind,
     body: Option<ReversiMessageBody>,
 }
 
+impl ReversiMessage {
+    pub fn error(id: Option<RequestId>, error: ReversiError) -> Self {
+        ReversiMessage {
+            id,
+            kind: ReversiMessageKind::ReversiError,
+            body: Some(ReversiMessageBody::ReversiError(error)),
+        }
+    }
+
+    /// The room a `Matched` message tells the player to go to
+    pub fn matched_room(&self) -> Option<&str> {
+        match self.body {
+            Some(ReversiMessageBody::Matched(ref room)) => Some(room),
+            _ => None,
+        }
+    }
+
+    pub fn authenticated(id: Option<RequestId>, name: String) -> Self {
+        ReversiMessage {
+            id,
+            kind: ReversiMessageKind::Authenticated,
+            body: Some(ReversiMessageBody::Authenticated(name)),
+        }
+    }
+
+    pub fn invite(id: Option<RequestId>, room: String, code: String) -> Self {
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("room", &room)
+            .append_pair("code", &code)
+            .finish();
+        let url = format!("{}?{}", INVITE_PAGE, query);
+        ReversiMessage {
+            id,
+            kind: ReversiMessageKind::Invite,
+            body: Some(ReversiMessageBody::Invite { room, code, url }),
+        }
+    }
+
+    pub fn leaderboard(id: Option<RequestId>, ratings: Vec<Rating>) -> Self {
+        ReversiMessage {
+            id,
+            kind: ReversiMessageKind::Leaderboard,
+            body: Some(ReversiMessageBody::Leaderboard(ratings)),
+        }
+    }
+
+    pub fn room_list(id: Option<RequestId>, rooms: Vec<(String, Room)>) -> Self {
+        ReversiMessage {
+            id,
+            kind: ReversiMessageKind::RoomList,
+            body: Some(ReversiMessageBody::RoomList(rooms)),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Copy, Clone)]
 enum ReversiMessageKind {
     Game,
@@ -63,40 +346,143 @@ enum ReversiMessageKind {
     Turn,
     Move,
     ReversiError,
+    ResumeToken,
+    RoomList,
+    DrawOffer,
+    TakebackRequest,
+    TakebackDeclined,
+    RematchOffer,
+    QueueStatus,
+    Matched,
+    Leaderboard,
+    Authenticated,
+    Invite,
+    RoomCreated,
+    RoomUpdated,
+    RoomClosed,
+    Chat,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub enum ReversiMessageBody {
-    GameOver((Game, Winner)),
+    GameOver((Game, Winner, Termination)),
     Turn(Color),
     Move(ReversiMove),
     GameStart(Color),
     Game(Game),
+    ResumeToken(Token),
+    ReversiError(ReversiError),
+    RoomList(Vec<(String, Room)>),
+    /// The color offering a draw
+    DrawOffer(Color),
+    /// The color asking for a takeback
+    TakebackRequest(Color),
+    /// The color declining it
+    TakebackDeclined(Color),
+    /// The color the player asking for a rematch had
+    RematchOffer(Color),
+    QueueStatus(QueueStatus),
+    /// The room made for a matched pair
+    Matched(String),
+    Leaderboard(Vec<Rating>),
+    /// The account name a session is logged in as
+    Authenticated(String),
+    Invite {
+        room: String,
+        code: String,
+        /// Link to `INVITE_PAGE` that joins the room with the code
+        url: String,
+    },
+    RoomCreated(RoomSummary),
+    RoomUpdated(RoomSummary),
+    /// The name of the room
+    RoomClosed(String),
+    Chat(ChatMessage),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct ChatMessage {
+    /// `None` for the lobby
+    pub room: Option<String>,
+    pub from: Uname,
+    pub text: String,
+    pub sent_at: Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RoomStatus {
+    /// Waiting for a second player
+    Waiting,
+    Playing,
+    Finished,
+}
+
+/// What the lobby shows of a room
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct RoomSummary {
+    pub name: String,
+    pub player1: Option<String>,
+    pub player2: Option<String>,
+    pub status: RoomStatus,
+    pub moves: usize,
+    pub locked: bool,
+}
+
+/// A room with its board, for `/api/rooms/{name}`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RoomDetail {
+    pub name: String,
+    pub status: RoomStatus,
+    pub player1: Option<Player>,
+    pub player2: Option<Player>,
+    pub time_control: Option<TimeControl>,
+    pub score: MatchScore,
+    pub game: Game,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub struct QueueStatus {
+    pub queued: bool,
+    pub time_control: Option<TimeControl>,
+    /// Players waiting for the same time control, oneself included
+    pub waiting: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum PlayerKind {
+    /// Logged in with an account
+    Human,
+    /// Playing under a free-text name, unrated
+    Guest,
+    Computer(Level),
+}
+
+impl PlayerKind {
+    fn is_computer(self) -> bool {
+        match self {
+            PlayerKind::Computer(_) => true,
+            PlayerKind::Human | PlayerKind::Guest => false,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
-struct Player {
+pub struct Player {
     id: Uid,
     name: Uname,
     color: Option<Color>,
+    kind: PlayerKind,
+    /// Filled in for the room list
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    rating: Option<i64>,
 }
 
-impl Player {
-    fn is_black(&self) -> bool {
-        if let Some(color) = self.color {
-            color.is_black()
-        } else {
-            false
-        }
-    }
-
-    fn is_white(&self) -> bool {
-        if let Some(color) = self.color {
-            color.is_white()
-        } else {
-            false
-        }
-    }
+/// Results of the games played in a room
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, Eq, PartialEq)]
+pub struct MatchScore {
+    pub player1: u32,
+    pub player2: u32,
+    pub draws: u32,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -105,8 +491,37 @@ pub struct Room {
     sessions: HashSet<usize>,
     #[serde(skip)]
     game: ReversiGame,
+    #[serde(skip)]
+    started_at: Option<Timestamp>,
+    #[serde(skip)]
+    clock: Option<Clock>,
+    #[serde(skip)]
+    draw_offer: Option<Color>,
+    #[serde(skip)]
+    takeback_request: Option<Color>,
+    /// The player asking for a rematch
+    #[serde(skip)]
+    rematch_offer: Option<Uid>,
+    #[serde(skip)]
+    private: bool,
+    #[serde(skip)]
+    password: Option<String>,
+    /// Unused invite codes
+    #[serde(skip)]
+    invites: HashSet<String>,
+    /// The latest chat messages, oldest first
+    #[serde(skip)]
+    chat: VecDeque<ChatMessage>,
+    /// Counts moves, takebacks and new games, so that a timer or a search
+    /// started for an earlier position can tell it is stale
+    #[serde(skip)]
+    generation: u64,
+    /// Whether a password is needed to enter
+    locked: bool,
     player1: Option<Player>,
     player2: Option<Player>,
+    time_control: Option<TimeControl>,
+    score: MatchScore,
 }
 
 impl Room {
@@ -114,53 +529,273 @@ impl Room {
         Room {
             sessions: HashSet::new(),
             game: ReversiGame::new(),
+            started_at: None,
+            clock: None,
+            draw_offer: None,
+            takeback_request: None,
+            rematch_offer: None,
+            private: false,
+            password: None,
+            invites: HashSet::new(),
+            chat: VecDeque::new(),
+            generation: 0,
+            locked: false,
             player1: None,
             player2: None,
+            time_control: None,
+            score: MatchScore::default(),
         }
     }
 
+    /// 盤面と残り時間
+    fn snapshot(&self) -> Game {
+        Game {
+            clock: self.clock.as_ref().map(|c| c.times(Instant::now())),
+            ..Game::from(&self.game)
+        }
+    }
+
+    fn player(&self, color: Color) -> Option<&Player> {
+        self.player1
+            .iter()
+            .chain(self.player2.iter())
+            .find(|p| p.color == Some(color))
+    }
+
     fn black(&self) -> Option<Uid> {
-        if let Some(ref p1) = self.player1 {
-            if p1.is_black() {
-                return Some(p1.id);
-            }
+        self.player(Color::Black).map(|p| p.id)
+    }
+
+    fn white(&self) -> Option<Uid> {
+        self.player(Color::White).map(|p| p.id)
+    }
+
+    fn color_of(&self, uid: Uid) -> Option<Color> {
+        self.player1
+            .iter()
+            .chain(self.player2.iter())
+            .find(|p| p.id == uid)
+            .and_then(|p| p.color)
+    }
+
+    fn is_player(&self, uid: Uid) -> bool {
+        self.player1
+            .iter()
+            .chain(self.player2.iter())
+            .any(|p| p.id == uid)
+    }
+
+    fn is_playing(&self) -> bool {
+        self.game.is_start && !self.game.is_over
+    }
+
+    fn summary(&self, name: &str) -> RoomSummary {
+        let status = if !self.game.is_start {
+            RoomStatus::Waiting
+        } else if self.game.is_over {
+            RoomStatus::Finished
+        } else {
+            RoomStatus::Playing
+        };
+        RoomSummary {
+            name: name.to_owned(),
+            player1: self.player1.as_ref().map(|p| p.name.clone()),
+            player2: self.player2.as_ref().map(|p| p.name.clone()),
+            status,
+            moves: self.game.moves().len(),
+            locked: self.locked,
+        }
+    }
+
+    fn detail(&self, name: &str) -> RoomDetail {
+        RoomDetail {
+            name: name.to_owned(),
+            status: self.summary(name).status,
+            player1: self.player1.clone(),
+            player2: self.player2.clone(),
+            time_control: self.time_control,
+            score: self.score,
+            game: self.snapshot(),
+        }
+    }
+
+    /// 非公開の部屋やパスワード付きの部屋には招待コードかパスワードが必要
+    fn admits(&self, password: Option<&str>, code: Option<&str>) -> bool {
+        if code.is_some_and(|code| self.invites.contains(code)) {
+            return true;
         }
-        if let Some(ref p2) = self.player1 {
-            if p2.is_black() {
-                return Some(p2.id);
+        match self.password {
+            Some(ref expected) => password == Some(expected.as_str()),
+            None => !self.private,
+        }
+    }
+
+    /// 人間同士の対局だけがレーティングの対象
+    fn is_rated(&self) -> bool {
+        match (&self.player1, &self.player2) {
+            (Some(p1), Some(p2)) => {
+                p1.kind == PlayerKind::Human && p2.kind == PlayerKind::Human && p1.name != p2.name
             }
+            _ => false,
         }
-        None
     }
 
-    fn white(&self) -> Option<Uid> {
-        if let Some(ref p1) = self.player1 {
-            if p1.is_white() {
-                return Some(p1.id);
+    /// 対局を開始して黒の時計を動かす
+    fn start(&mut self) {
+        self.generation += 1;
+        self.game.is_start = true;
+        self.started_at = Some(storage::now());
+        self.clock = self.time_control.map(Clock::new);
+        if let Some(clock) = self.clock.as_mut() {
+            clock.start(Color::Black, Instant::now());
+        }
+    }
+
+    /// 色を入れ替えて新しい対局を始める
+    /// 黒と白のプレイヤーのidを返す
+    fn restart(&mut self) -> Option<(Uid, Uid)> {
+        for player in self.player1.iter_mut().chain(self.player2.iter_mut()) {
+            player.color = player.color.map(Color::opposite);
+        }
+        let ids = (self.black()?, self.white()?);
+        self.game = ReversiGame::new();
+        self.draw_offer = None;
+        self.takeback_request = None;
+        self.rematch_offer = None;
+        self.start();
+        Some(ids)
+    }
+
+    fn record_result(&mut self, winner: Winner) {
+        match winner {
+            None => self.score.draws += 1,
+            Some(color) if self.player1.as_ref().and_then(|p| p.color) == Some(color) => {
+                self.score.player1 += 1
             }
+            Some(_) => self.score.player2 += 1,
         }
-        if let Some(ref p2) = self.player1 {
-            if p2.is_white() {
-                return Some(p2.id);
+    }
+
+    /// `color`の最後の手まで巻き戻す
+    fn take_back(&mut self, color: Color) {
+        self.generation += 1;
+        while let Some(m) = self.game.undo() {
+            if m.color == color {
+                break;
             }
         }
-        None
+        self.draw_offer = None;
+        self.takeback_request = None;
+        let now = Instant::now();
+        let turn = self.game.turn;
+        if let Some(clock) = self.clock.as_mut() {
+            clock.stop(now);
+            clock.start(turn, now);
+        }
+    }
+
+    fn archive(&self, name: &str, termination: Termination, winner: Winner) -> ArchivedGame {
+        let player_name = |color| {
+            self.player(color)
+                .map(|p: &Player| p.name.clone())
+                .unwrap_or_default()
+        };
+        let (black_discs, white_discs, _) = self.game.board.count_piece();
+        ArchivedGame {
+            id: None,
+            room: name.to_owned(),
+            black: player_name(Color::Black),
+            white: player_name(Color::White),
+            moves: self.game.to_transcript(),
+            black_discs: black_discs as u32,
+            white_discs: white_discs as u32,
+            winner,
+            termination,
+            started_at: self.started_at.unwrap_or_else(storage::now),
+            finished_at: storage::now(),
+        }
     }
 }
 
 type RoomMap = HashMap<String, Room>;
 
+/// A player waiting in the matchmaking queue
+#[derive(Clone, Debug)]
+struct QueueEntry {
+    uid: Uid,
+    uname: Uname,
+    kind: PlayerKind,
+    time_control: Option<TimeControl>,
+    rating: f64,
+    band: Option<u32>,
+}
+
+impl QueueEntry {
+    /// Whether both players accept each other's time control and rating
+    fn accepts(&self, other: &QueueEntry) -> bool {
+        let diff = (self.rating - other.rating).abs();
+        let within = |band: Option<u32>| band.is_none_or(|band| diff <= f64::from(band));
+        self.time_control == other.time_control && within(self.band) && within(other.band)
+    }
+}
+
+/// The account a session token was issued for
+#[derive(Clone, Debug)]
+struct LoginSession {
+    name: Uname,
+    expires: Instant,
+}
+
+/// The seat a resume token gives back
+#[derive(Clone, Debug)]
+struct Seat {
+    room: String,
+    uid: Uid,
+}
+
 trait RoomMapImpl {
-    fn make_room(&mut self, room_name: String, Uid, Uname, Option<Color>);
-    fn join(&mut self, room_name: String, Uid, Uname) -> Result<(usize, usize), String>;
-    fn leave(&mut self, Uid);
+    /// Fails once `max_rooms` rooms are open
+    #[allow(clippy::too_many_arguments)]
+    fn make_room(
+        &mut self,
+        room_name: String,
+        uid: Uid,
+        uname: Uname,
+        kind: PlayerKind,
+        color: Option<Color>,
+        time_control: Option<TimeControl>,
+        max_rooms: usize,
+    ) -> Result<(), ReversiError>;
+    fn join(
+        &mut self,
+        room_name: String,
+        uid: Uid,
+        uname: Uname,
+        kind: PlayerKind,
+    ) -> Result<(usize, usize), ReversiError>;
+    fn watch(&mut self, room_name: String, uid: Uid) -> Result<(), ReversiError>;
+    /// Leaves every room. Rooms in `held` have a seat held for `/resume`,
+    /// so `uid`'s seat there is kept and they are not removed when empty.
+    fn leave(&mut self, uid: Uid, held: &HashSet<String>);
 }
 
 impl RoomMapImpl for RoomMap {
-    fn make_room(&mut self, room_name: String, uid: Uid, uname: Uname, color: Option<Color>) {
+    fn make_room(
+        &mut self,
+        room_name: String,
+        uid: Uid,
+        uname: Uname,
+        kind: PlayerKind,
+        color: Option<Color>,
+        time_control: Option<TimeControl>,
+        max_rooms: usize,
+    ) -> Result<(), ReversiError> {
+        if self.len() >= max_rooms {
+            return Err(ReversiError::TooManyRooms);
+        }
         if self.get_mut(&room_name).is_some() {
-            eprintln!("Room {} is already created", room_name);
-            return;
+            warn!("Room {} is already created", room_name);
+            return Err(ReversiError::RoomExists(room_name));
         }
 
         let mut sessions = HashSet::new();
@@ -170,12 +805,15 @@ impl RoomMapImpl for RoomMap {
             player1: Some(Player {
                 id: uid,
                 name: uname,
-                color: color,
+                color,
+                kind,
+                rating: None,
             }),
-            player2: None,
-            game: ReversiGame::new(),
+            time_control,
+            ..Room::new()
         };
         self.insert(room_name.clone(), room);
+        Ok(())
     }
 
     fn join(
@@ -183,11 +821,17 @@ impl RoomMapImpl for RoomMap {
         room_name: String,
         uid: Uid,
         uname: Uname,
-    ) -> Result<(usize, usize), String> {
+        kind: PlayerKind,
+    ) -> Result<(usize, usize), ReversiError> {
         //ゲームルームが存在していないか、すでに満員の場合は終了
-        if !self.contains_key(&room_name) || self.get(&room_name).unwrap().player2.is_some() {
-            println!("Failed enter the room");
-            return Err("Failed enter the room".to_string());
+        match self.get(&room_name) {
+            None => return Err(ReversiError::NoSuchRoom(room_name)),
+            Some(room) if room.is_player(uid) => return Err(ReversiError::AlreadySeated),
+            Some(room) if room.player2.is_some() || room.player1.is_none() => {
+                warn!("Failed enter the room");
+                return Err(ReversiError::RoomFull(room_name));
+            }
+            _ => {}
         }
 
         // すべてのゲームルームからセッションを削除
@@ -209,14 +853,19 @@ impl RoomMapImpl for RoomMap {
             }
         }
 
-        println!("{}: Someone connected", room_name);
+        info!("{}: Someone connected", room_name);
 
         // プレイヤーの登録
-        self.get_mut(&room_name).unwrap().sessions.insert(uid);
+        // コンピュータはセッションを持たないのでsessionsには入れない
+        if !kind.is_computer() {
+            self.get_mut(&room_name).unwrap().sessions.insert(uid);
+        }
         self.get_mut(&room_name).unwrap().player2 = Some(Player {
             id: uid,
             name: uname,
             color: None,
+            kind,
+            rating: None,
         });
 
         //1Pの色とは逆の色を入れる.
@@ -228,12 +877,11 @@ impl RoomMapImpl for RoomMap {
             let Room {
                 ref mut player1,
                 ref mut player2,
-                ref mut game,
                 ..
             } = self.get_mut(&room_name).unwrap();
             let player1 = player1.as_mut().unwrap();
             let player2 = player2.as_mut().unwrap();
-            if let Some(color) = player1.color.clone() {
+            if let Some(color) = player1.color {
                 let color = if color.is_black() {
                     Color::White
                 } else {
@@ -251,14 +899,31 @@ impl RoomMapImpl for RoomMap {
                 white_id = player1.id;
                 black_id = player2.id;
             }
-            game.is_start = true;
         }
+        self.get_mut(&room_name).unwrap().start();
         Ok((black_id, white_id))
     }
 
+    fn watch(&mut self, room_name: String, uid: Uid) -> Result<(), ReversiError> {
+        if !self.contains_key(&room_name) {
+            return Err(ReversiError::NoSuchRoom(room_name));
+        }
+
+        // 観戦していた他のゲームルームからは抜ける
+        for room in self.values_mut() {
+            if !room.is_player(uid) {
+                room.sessions.remove(&uid);
+            }
+        }
+
+        info!("{}: Someone is watching", room_name);
+        self.get_mut(&room_name).unwrap().sessions.insert(uid);
+        Ok(())
+    }
+
     // 各Roomのセッションからもユーザーを削除し
     // セッションが空になったRoomを取り除く
-    fn leave(&mut self, uid: Uid) {
+    fn leave(&mut self, uid: Uid, held: &HashSet<String>) {
         self.retain(
             |name,
              Room {
@@ -267,6 +932,11 @@ impl RoomMapImpl for RoomMap {
                  player2,
                  ..
              }| {
+                // 席が確保されている部屋は接続だけを外し、部屋も残す
+                if held.contains(name) {
+                    sessions.remove(&uid);
+                    return true;
+                }
                 // 退出するユーザーがプレイヤーである場合は
                 // 対応するプレイヤー(player1、またはplayer2)をNoneにする
                 *player1 = player1.take().filter(|p1| p1.id != uid);
@@ -274,7 +944,7 @@ impl RoomMapImpl for RoomMap {
 
                 sessions.remove(&uid);
                 if sessions.is_empty() {
-                    println!("Remove Room: {}", name);
+                    info!("Remove Room: {}", name);
                 }
                 !sessions.is_empty()
             },
@@ -286,124 +956,826 @@ pub struct GameServer {
     sessions: HashMap<usize, Recipient<ReversiMessage>>,
     rooms: RoomMap,
     rng: ThreadRng,
+    archive: Box<dyn Storage>,
+    seats: HashMap<Token, Seat>,
+    resume_grace: Duration,
+    max_rooms: usize,
+    engine: Addr<EngineWorker>,
+    /// Kept apart from `engine` so that analyses do not hold up computer players
+    analyzer: Addr<EngineWorker>,
+    pending_analyses: usize,
+    passwords: Addr<PasswordWorker>,
+    /// Oldest first
+    queue: Vec<QueueEntry>,
+    /// Session tokens issued at login
+    logins: HashMap<Token, LoginSession>,
+    session_lifetime: Duration,
+    /// Sessions authenticated with a session token
+    users: HashMap<Uid, Uname>,
+    /// Sessions subscribed to lobby events
+    lobby: HashSet<Uid>,
+    /// Public rooms as last told to the lobby
+    lobby_rooms: HashMap<String, RoomSummary>,
+    /// The latest lobby chat messages, oldest first
+    lobby_chat: VecDeque<ChatMessage>,
 }
 
 impl Default for GameServer {
     fn default() -> GameServer {
+        GameServer::new(Box::new(MemoryArchive::new()))
+    }
+}
+
+impl GameServer {
+    pub fn new(archive: Box<dyn Storage>) -> GameServer {
         GameServer {
             sessions: HashMap::new(),
             rooms: HashMap::new(),
             rng: rand::thread_rng(),
+            archive,
+            seats: HashMap::new(),
+            resume_grace: RESUME_GRACE,
+            max_rooms: MAX_ROOMS,
+            engine: SyncArbiter::start(worker::WORKER_THREADS, || EngineWorker),
+            analyzer: SyncArbiter::start(worker::ANALYSIS_THREADS, || EngineWorker),
+            pending_analyses: 0,
+            passwords: SyncArbiter::start(worker::PASSWORD_THREADS, || PasswordWorker),
+            queue: Vec::new(),
+            logins: HashMap::new(),
+            session_lifetime: SESSION_LIFETIME,
+            users: HashMap::new(),
+            lobby: HashSet::new(),
+            lobby_rooms: HashMap::new(),
+            lobby_chat: VecDeque::new(),
         }
     }
-}
 
-impl GameServer {
+    pub fn with_resume_grace(mut self, resume_grace: Duration) -> GameServer {
+        self.resume_grace = resume_grace;
+        self
+    }
+
+    pub fn with_max_rooms(mut self, max_rooms: usize) -> GameServer {
+        self.max_rooms = max_rooms;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_session_lifetime(mut self, session_lifetime: Duration) -> GameServer {
+        self.session_lifetime = session_lifetime;
+        self
+    }
+
     fn send_reversi_message(&self, message: ReversiMessage, id: Uid) {
         if let Some(addr) = self.sessions.get(&id) {
             let _ = addr.do_send(message.clone());
         }
     }
-    fn send_reversi_message_room(
-        &self,
-        room: &str,
-        message: ReversiMessage,
-        skip_id: Option<usize>,
-    ) {
-        if let Some(Room { sessions, .. }) = self.rooms.get(room) {
-            for id in sessions {
-                if let Some(skip_id) = skip_id {
-                    if *id == skip_id {
-                        continue;
-                    }
-                }
-                self.send_reversi_message(message.clone(), *id);
+    fn send_game_start(&mut self, room: &str, black_id: Uid, white_id: Uid) {
+        for &(id, color) in &[(black_id, Color::Black), (white_id, Color::White)] {
+            self.send_reversi_message(
+                ReversiMessage {
+                    id: None,
+                    kind: ReversiMessageKind::GameStart,
+                    body: Some(ReversiMessageBody::GameStart(color)),
+                },
+                id,
+            );
+            // 接続が切れても席に戻れるようにトークンを渡す
+            if self.sessions.contains_key(&id) {
+                let token = self.generate_token();
+                self.seats.insert(
+                    token.clone(),
+                    Seat {
+                        room: room.to_owned(),
+                        uid: id,
+                    },
+                );
+                self.send_reversi_message(
+                    ReversiMessage {
+                        id: None,
+                        kind: ReversiMessageKind::ResumeToken,
+                        body: Some(ReversiMessageBody::ResumeToken(token)),
+                    },
+                    id,
+                );
             }
         }
     }
-}
-
-impl Actor for GameServer {
-    type Context = Context<Self>;
-}
-
-impl Handler<Connect<ReversiMessage>> for GameServer {
-    type Result = usize;
-
-    fn handle(&mut self, msg: Connect<ReversiMessage>, _: &mut Context<Self>) -> Self::Result {
-        println!("Someone joined");
-
-        // register session with random id
-        let id = self.rng.gen::<usize>();
-        self.sessions.insert(id, msg.addr);
 
-        // send id back
-        id
+    /// チャットの発言者名
+    /// ログインしていればアカウント名、部屋のプレイヤーならその名前
+    fn chat_name(&self, uid: Uid, room: Option<&Room>) -> Uname {
+        if let Some(name) = self.users.get(&uid) {
+            return name.clone();
+        }
+        room.and_then(|room| {
+            room.player1
+                .iter()
+                .chain(room.player2.iter())
+                .find(|p| p.id == uid)
+                .map(|p| p.name.clone())
+        })
+        .unwrap_or_else(|| "Guest".to_owned())
     }
-}
-
-impl Handler<Disconnect> for GameServer {
-    type Result = ();
-
-    fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
-        println!("Someone disconnected");
 
-        // remove address
-        if self.sessions.remove(&msg.id).is_some() {
-            self.rooms.leave(msg.id);
+    /// 後から来た人にチャットの履歴を送る
+    fn send_scrollback(&self, chat: &VecDeque<ChatMessage>, uid: Uid) {
+        for message in chat {
+            self.send_reversi_message(
+                ReversiMessage {
+                    id: None,
+                    kind: ReversiMessageKind::Chat,
+                    body: Some(ReversiMessageBody::Chat(message.clone())),
+                },
+                uid,
+            );
+        }
+    }
+
+    /// アカウントのあるプレイヤーにレーティングを付ける
+    fn fill_ratings<'a, I: Iterator<Item = &'a mut Player>>(&self, players: I) {
+        for player in players {
+            if player.kind == PlayerKind::Human {
+                player.rating = Some(self.rating(&player.name).rating.round() as i64);
+            }
+        }
+    }
+
+    /// 前回から変わった公開部屋をロビーに知らせる
+    fn update_lobby(&mut self) {
+        let rooms: HashMap<String, RoomSummary> = self
+            .rooms
+            .iter()
+            .filter(|(_, room)| !room.private)
+            .map(|(name, room)| (name.clone(), room.summary(name)))
+            .collect();
+        let mut events = Vec::new();
+        for (name, summary) in &rooms {
+            match self.lobby_rooms.get(name) {
+                None => events.push((
+                    ReversiMessageKind::RoomCreated,
+                    ReversiMessageBody::RoomCreated(summary.clone()),
+                )),
+                Some(previous) if previous != summary => events.push((
+                    ReversiMessageKind::RoomUpdated,
+                    ReversiMessageBody::RoomUpdated(summary.clone()),
+                )),
+                Some(_) => {}
+            }
+        }
+        for name in self.lobby_rooms.keys() {
+            if !rooms.contains_key(name) {
+                events.push((
+                    ReversiMessageKind::RoomClosed,
+                    ReversiMessageBody::RoomClosed(name.clone()),
+                ));
+            }
+        }
+        self.lobby_rooms = rooms;
+
+        for (kind, body) in events {
+            for &uid in &self.lobby {
+                self.send_reversi_message(
+                    ReversiMessage {
+                        id: None,
+                        kind,
+                        body: Some(body.clone()),
+                    },
+                    uid,
+                );
+            }
+        }
+    }
+
+    fn check_admission(
+        &self,
+        room: &str,
+        password: Option<&str>,
+        code: Option<&str>,
+    ) -> Result<(), ReversiError> {
+        match self.rooms.get(room) {
+            Some(r) if !r.admits(password, code) => Err(ReversiError::RoomLocked(room.to_owned())),
+            _ => Ok(()),
+        }
+    }
+
+    fn generate_token(&mut self) -> Token {
+        format!(
+            "{:016x}{:016x}",
+            self.rng.gen::<u64>(),
+            self.rng.gen::<u64>()
+        )
+    }
+
+    /// ログインしていればアカウント名で、していなければゲストとして名乗る
+    /// 登録済みの名前はゲストには使えない
+    fn identify(&self, uid: Uid, uname: Uname) -> Result<(Uname, PlayerKind), ReversiError> {
+        if let Some(name) = self.users.get(&uid) {
+            return Ok((name.clone(), PlayerKind::Human));
+        }
+        match self.account(&uname)? {
+            None => Ok((uname, PlayerKind::Guest)),
+            Some(_) => Err(ReversiError::NameTaken(uname)),
+        }
+    }
+
+    fn account(&self, name: &str) -> Result<Option<Account>, ReversiError> {
+        self.archive.account(name).map_err(|e| {
+            error!("Failed to look up {}: {}", name, e);
+            ReversiError::Storage
+        })
+    }
+
+    /// セッショントークンを発行する
+    /// 期限切れのトークンはここでまとめて捨てる
+    fn log_in(&mut self, name: Uname) -> Token {
+        let now = Instant::now();
+        self.logins.retain(|_, login| login.expires > now);
+        let token = self.generate_token();
+        let expires = now + self.session_lifetime;
+        self.logins
+            .insert(token.clone(), LoginSession { name, expires });
+        token
+    }
+
+    fn rating(&self, name: &str) -> Rating {
+        match self.archive.rating(name) {
+            Ok(Some(rating)) => rating,
+            Ok(None) => Rating::new(name),
+            Err(e) => {
+                error!("Failed to read the rating of {}: {}", name, e);
+                Rating::new(name)
+            }
+        }
+    }
+
+    /// 対局結果で両者のレーティングを更新する
+    fn rate(&mut self, black: &str, white: &str, winner: Winner) {
+        let mut black = self.rating(black);
+        let mut white = self.rating(white);
+        rating::rate_game(&mut black, &mut white, winner);
+        for rating in &[black, white] {
+            if let Err(e) = self.archive.store_rating(rating) {
+                error!("Failed to store the rating of {}: {}", rating.name, e);
+            }
+        }
+    }
+
+    /// 待ち行列の人数を同じ持ち時間で待っているプレイヤーに知らせる
+    fn send_queue_status(&self, time_control: Option<TimeControl>) {
+        let waiting: Vec<Uid> = self
+            .queue
+            .iter()
+            .filter(|entry| entry.time_control == time_control)
+            .map(|entry| entry.uid)
+            .collect();
+        for &uid in &waiting {
+            self.send_reversi_message(
+                ReversiMessage {
+                    id: None,
+                    kind: ReversiMessageKind::QueueStatus,
+                    body: Some(ReversiMessageBody::QueueStatus(QueueStatus {
+                        queued: true,
+                        time_control,
+                        waiting: waiting.len(),
+                    })),
+                },
+                uid,
+            );
+        }
+    }
+
+    /// 使われていない部屋名を作る
+    fn generate_room_name(&mut self) -> String {
+        loop {
+            let name = format!("match-{:06x}", self.rng.gen_range(0, 0x100_0000));
+            if !self.rooms.contains_key(&name) {
+                return name;
+            }
+        }
+    }
+
+    /// 待っているプレイヤー同士で部屋を作って対局を始める
+    fn start_match(
+        &mut self,
+        first: QueueEntry,
+        second: QueueEntry,
+        ctx: &mut Context<Self>,
+    ) -> Result<(), ReversiError> {
+        let name = self.generate_room_name();
+        let color = if self.rng.gen::<bool>() {
+            Color::Black
+        } else {
+            Color::White
+        };
+        info!("Matched {} and {} in {}", first.uname, second.uname, name);
+        self.rooms.make_room(
+            name.clone(),
+            first.uid,
+            first.uname,
+            first.kind,
+            Some(color),
+            first.time_control,
+            self.max_rooms,
+        )?;
+        let (black_id, white_id) =
+            self.rooms
+                .join(name.clone(), second.uid, second.uname, second.kind)?;
+        for &uid in &[first.uid, second.uid] {
+            self.send_reversi_message(
+                ReversiMessage {
+                    id: None,
+                    kind: ReversiMessageKind::Matched,
+                    body: Some(ReversiMessageBody::Matched(name.clone())),
+                },
+                uid,
+            );
+        }
+        self.update_lobby();
+        self.send_game_start(&name, black_id, white_id);
+        self.schedule_flag(&name, ctx);
+        Ok(())
+    }
+
+    /// 部屋に空きがある限り、条件の合うプレイヤー同士を対局させる
+    /// 空きがなければ部屋が空くまで待ち行列に残しておく
+    fn match_queue(&mut self, ctx: &mut Context<Self>) {
+        while self.rooms.len() < self.max_rooms {
+            let pair = self.queue.iter().enumerate().find_map(|(i, first)| {
+                self.queue[i + 1..]
+                    .iter()
+                    .position(|second| first.accepts(second))
+                    .map(|j| (i, i + 1 + j))
+            });
+            let (i, j) = match pair {
+                Some(pair) => pair,
+                None => return,
+            };
+            let second = self.queue.remove(j);
+            let first = self.queue.remove(i);
+            if let Err(e) = self.start_match(first, second, ctx) {
+                error!("Failed to start a match: {}", e);
+            }
+        }
+    }
+
+    /// 猶予期間内に戻らなかったプレイヤーの負けとして対局を終了させる
+    /// 対局が先に終わっていても、誰もいなくなった部屋はここで片付ける
+    fn expire_seat(&mut self, room_name: &str, uid: Uid, ctx: &mut Context<Self>) {
+        // 戻ってきていれば席は新しい uid のものになっている
+        let token = self
+            .seats
+            .iter()
+            .find(|(_, seat)| seat.uid == uid && seat.room == room_name)
+            .map(|(token, _)| token.clone());
+        if let Some(token) = token {
+            self.seats.remove(&token);
+            let color = self
+                .rooms
+                .get(room_name)
+                .filter(|room| room.is_playing())
+                .and_then(|room| room.color_of(uid));
+            if let Some(color) = color {
+                self.finish_game(room_name, Termination::Abandoned, Some(color.opposite()));
+            }
+        }
+        self.leave_rooms(uid);
+        self.update_lobby();
+        self.match_queue(ctx);
+    }
+
+    /// 猶予期間中の席がある部屋は、空になっても残しておく
+    fn leave_rooms(&mut self, uid: Uid) {
+        let held = self.seats.values().map(|seat| seat.room.clone()).collect();
+        self.rooms.leave(uid, &held);
+    }
+
+    /// コンピュータの手番であれば手を選んで打たせる
+    fn play_computer(&mut self, room: &str, ctx: &mut Context<Self>) {
+        let room_state = match self.rooms.get(room) {
+            Some(room) if room.is_playing() => room,
+            _ => return,
+        };
+        let player = match room_state.player(room_state.game.turn) {
+            Some(player) => player,
+            None => return,
+        };
+        if let PlayerKind::Computer(level) = player.kind {
+            // 探索は別スレッドで行い、結果が届いたら手を指す
+            let id = player.id;
+            let generation = room_state.generation;
+            let room = room.to_owned();
+            self.engine
+                .send(ChooseMove {
+                    game: room_state.game.clone(),
+                    level,
+                })
+                .into_actor(self)
+                .then(move |res, _, ctx| {
+                    if let Ok(Some(reversi_move)) = res {
+                        ctx.notify(ComputerMove {
+                            id,
+                            room,
+                            generation,
+                            reversi_move,
+                        });
+                    }
+                    actix::fut::ok(())
+                })
+                .spawn(ctx);
+        }
+    }
+
+    /// 手番側の持ち時間が切れる頃に確認するタイマーを仕掛ける
+    fn schedule_flag(&self, room_name: &str, ctx: &mut Context<Self>) {
+        let room = match self.rooms.get(room_name) {
+            Some(room) if room.is_playing() => room,
+            _ => return,
+        };
+        if let Some(ref clock) = room.clock {
+            let wait = clock.time_left(room.game.turn, Instant::now());
+            let generation = room.generation;
+            let room_name = room_name.to_owned();
+            ctx.run_later(wait, move |act, ctx| {
+                act.check_flag(&room_name, generation, ctx)
+            });
+        }
+    }
+
+    fn check_flag(&mut self, room_name: &str, generation: u64, ctx: &mut Context<Self>) {
+        let turn = match self.rooms.get(room_name) {
+            // 手が進んだり戻ったりしていれば別のタイマーが仕掛けられている
+            Some(room) if room.is_playing() && room.generation == generation => room.game.turn,
+            _ => return,
+        };
+        if self.is_flagged(room_name) {
+            self.finish_game(room_name, Termination::Timeout, Some(turn.opposite()));
+        } else {
+            self.schedule_flag(room_name, ctx);
+        }
+    }
+
+    fn is_flagged(&self, room_name: &str) -> bool {
+        self.rooms.get(room_name).is_some_and(|room| {
+            room.clock
+                .as_ref()
+                .is_some_and(|clock| clock.is_flagged(room.game.turn, Instant::now()))
+        })
+    }
+
+    /// 対局を終了させて結果を通知し、棋譜を保存する
+    fn finish_game(&mut self, room_name: &str, termination: Termination, winner: Winner) {
+        self.seats.retain(|_, seat| seat.room != room_name);
+        let (game, record, rated) = match self.rooms.get_mut(room_name) {
+            Some(room) => {
+                room.game.is_over = true;
+                if let Some(clock) = room.clock.as_mut() {
+                    clock.stop(Instant::now());
+                }
+                if termination != Termination::Aborted {
+                    room.record_result(winner);
+                }
+                (
+                    room.snapshot(),
+                    room.archive(room_name, termination, winner),
+                    termination != Termination::Aborted && room.is_rated(),
+                )
+            }
+            None => return,
+        };
+        self.send_reversi_message_room(
+            room_name,
+            ReversiMessage {
+                id: None,
+                kind: ReversiMessageKind::GameOver,
+                body: Some(ReversiMessageBody::GameOver((game, winner, termination))),
+            },
+            None,
+        );
+        self.update_lobby();
+        if let Err(e) = self.archive.store(&record) {
+            error!("Failed to archive the game in {}: {}", room_name, e);
+        }
+        if rated {
+            self.rate(&record.black, &record.white, winner);
+        }
+    }
+
+    fn send_reversi_message_room(
+        &self,
+        room: &str,
+        message: ReversiMessage,
+        skip_id: Option<usize>,
+    ) {
+        if let Some(Room { sessions, .. }) = self.rooms.get(room) {
+            for id in sessions {
+                if let Some(skip_id) = skip_id {
+                    if *id == skip_id {
+                        continue;
+                    }
+                }
+                self.send_reversi_message(message.clone(), *id);
+            }
         }
     }
 }
 
-impl Handler<ClientReversiMoveMessage> for GameServer {
+impl Actor for GameServer {
+    type Context = Context<Self>;
+}
+
+impl Handler<Connect<ReversiMessage>> for GameServer {
+    type Result = usize;
+
+    fn handle(&mut self, msg: Connect<ReversiMessage>, _: &mut Context<Self>) -> Self::Result {
+        info!("Someone joined");
+
+        // register session with random id
+        let id = self.rng.gen::<usize>();
+        self.sessions.insert(id, msg.addr);
+
+        // send id back
+        id
+    }
+}
+
+impl Handler<Disconnect> for GameServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Disconnect, ctx: &mut Context<Self>) {
+        info!("Someone disconnected");
+
+        self.users.remove(&msg.id);
+        self.lobby.remove(&msg.id);
+        if let Some(i) = self.queue.iter().position(|entry| entry.uid == msg.id) {
+            let entry = self.queue.remove(i);
+            self.send_queue_status(entry.time_control);
+        }
+
+        // remove address
+        if self.sessions.remove(&msg.id).is_some() {
+            // 対局中のプレイヤーの席は猶予期間の間は残しておく
+            let rooms = &self.rooms;
+            self.seats.retain(|_, seat| {
+                seat.uid != msg.id || rooms.get(&seat.room).is_some_and(Room::is_playing)
+            });
+            let held: Vec<String> = self
+                .seats
+                .values()
+                .filter(|seat| seat.uid == msg.id)
+                .map(|seat| seat.room.clone())
+                .collect();
+            self.leave_rooms(msg.id);
+            self.update_lobby();
+            self.match_queue(ctx);
+            for room_name in held {
+                let uid = msg.id;
+                ctx.run_later(self.resume_grace, move |act, ctx| {
+                    act.expire_seat(&room_name, uid, ctx)
+                });
+            }
+        }
+    }
+}
+
+impl Handler<ComputerMove> for GameServer {
     type Result = ();
 
-    fn handle(&mut self, msg: ClientReversiMoveMessage, _: &mut Context<Self>) {
+    fn handle(&mut self, msg: ComputerMove, ctx: &mut Context<Self>) {
+        // 考えている間に投了や待ったで局面が変わっていたら捨てる
+        let current = self
+            .rooms
+            .get(&msg.room)
+            .is_some_and(|room| room.is_playing() && room.generation == msg.generation);
+        if !current {
+            return;
+        }
+        let reversi_move = ClientReversiMoveMessage {
+            id: msg.id,
+            reversi_move: msg.reversi_move,
+            room: msg.room,
+        };
+        if let Err(e) = Handler::<ClientReversiMoveMessage>::handle(self, reversi_move, ctx) {
+            warn!("Computer move was rejected: {}", e);
+        }
+    }
+}
+
+impl Handler<ClientReversiMoveMessage> for GameServer {
+    type Result = Result<(), ReversiError>;
+
+    fn handle(&mut self, msg: ClientReversiMoveMessage, ctx: &mut Context<Self>) -> Self::Result {
         use self::{ReversiMessage, ReversiMessageBody, ReversiMessageKind};
-        let mut is_over = false;
-        if self.rooms.contains_key(&msg.room) {
-            let result = {
-                let room = self.rooms.get_mut(&msg.room).unwrap();
-                println!("{:?}", room.game.board);
-                if room.game.is_start {
-                    let result = room.game.put_piece(msg.reversi_move);
-                    if result.is_ok() && !room.game.is_over {
-                        room.game.change_turn();
-                        is_over = room.game.is_over;
+        // 時間切れの後に届いた手は受け付けない
+        if self.is_flagged(&msg.room) && self.rooms[&msg.room].is_playing() {
+            let turn = self.rooms[&msg.room].game.turn;
+            self.finish_game(&msg.room, Termination::Timeout, Some(turn.opposite()));
+            return Err(ReversiError::GameOver);
+        }
+        let is_over = {
+            let room = self
+                .rooms
+                .get_mut(&msg.room)
+                .ok_or_else(|| ReversiError::NoSuchRoom(msg.room.clone()))?;
+            debug!("{:?}", room.game.board);
+            if !room.game.is_start {
+                return Err(ReversiError::GameNotStarted);
+            }
+            // 観戦者や相手の手番では打てない
+            match room.color_of(msg.id) {
+                None => return Err(ReversiError::NotAPlayer),
+                Some(color) if color != room.game.turn && !room.game.is_over => {
+                    return Err(ReversiError::NotYourTurn)
+                }
+                _ => {}
+            }
+            room.game.put_piece(msg.reversi_move)?;
+            room.generation += 1;
+            room.game.change_turn();
+            // 手を指したら引き分けや待ったの申し出は取り消し
+            room.draw_offer = None;
+            room.takeback_request = None;
+            let now = Instant::now();
+            let turn = room.game.turn;
+            if let Some(clock) = room.clock.as_mut() {
+                clock.punch(now);
+                clock.start(turn, now);
+            }
+            room.game.is_over
+        };
+
+        if is_over {
+            let winner = self.rooms.get(&msg.room).unwrap().game.winner();
+            self.finish_game(&msg.room, Termination::Normal, winner);
+        } else {
+            self.send_reversi_message_room(
+                &msg.room,
+                ReversiMessage {
+                    id: None,
+                    kind: ReversiMessageKind::Game,
+                    body: Some(ReversiMessageBody::Game(
+                        self.rooms.get(&msg.room).unwrap().snapshot(),
+                    )),
+                },
+                None,
+            );
+            self.update_lobby();
+            self.schedule_flag(&msg.room, ctx);
+            self.play_computer(&msg.room, ctx);
+        }
+        Ok(())
+    }
+}
+
+/// What a `PlayerAction` leads to
+enum ActionOutcome {
+    Finish(Termination, Winner),
+    /// Tell the room about an offer or an answer
+    Notify(ReversiMessageKind, ReversiMessageBody),
+    Rewind,
+    /// Start the next game of the match
+    Restart(Uid, Uid),
+}
+
+impl Handler<PlayerAction> for GameServer {
+    type Result = Result<(), ReversiError>;
+
+    fn handle(&mut self, msg: PlayerAction, ctx: &mut Context<Self>) -> Self::Result {
+        let PlayerAction { id, room, action } = msg;
+        let outcome = {
+            let room_state = self
+                .rooms
+                .get_mut(&room)
+                .ok_or_else(|| ReversiError::NoSuchRoom(room.clone()))?;
+            if !room_state.game.is_start {
+                return Err(ReversiError::GameNotStarted);
+            }
+            if room_state.game.is_over && action != Action::Rematch {
+                return Err(ReversiError::GameOver);
+            }
+            let color = room_state.color_of(id).ok_or(ReversiError::NotAPlayer)?;
+            match action {
+                Action::Resign => {
+                    ActionOutcome::Finish(Termination::Resigned, Some(color.opposite()))
+                }
+                Action::OfferDraw => {
+                    room_state.draw_offer = Some(color);
+                    ActionOutcome::Notify(
+                        ReversiMessageKind::DrawOffer,
+                        ReversiMessageBody::DrawOffer(color),
+                    )
+                }
+                Action::AcceptDraw => {
+                    if room_state.draw_offer != Some(color.opposite()) {
+                        return Err(ReversiError::NoDrawOffer);
                     }
-                    result
-                } else {
-                    Err("Game is not started".to_string())
+                    ActionOutcome::Finish(Termination::DrawAgreed, None)
                 }
-            };
-            println!("{:?}", result);
-            if result.is_ok() {
-                if is_over {
-                    let game = &self.rooms.get(&msg.room).unwrap().game;
-                    let winner = game.winner();
-                    let game = Game::from(game);
-                    self.send_reversi_message_room(
-                        &msg.room,
-                        ReversiMessage {
-                            kind: ReversiMessageKind::GameOver,
-                            body: Some(ReversiMessageBody::GameOver((game, winner))),
-                        },
-                        None,
-                    );
-                } else {
-                    self.send_reversi_message_room(
-                        &msg.room,
-                        ReversiMessage {
-                            kind: ReversiMessageKind::Game,
-                            body: Some(ReversiMessageBody::Game(Game::from(
-                                &self.rooms.get(&msg.room).unwrap().game,
-                            ))),
-                        },
-                        None,
-                    );
+                Action::Abort => {
+                    if !room_state.game.history().is_empty() {
+                        return Err(ReversiError::CannotAbort);
+                    }
+                    ActionOutcome::Finish(Termination::Aborted, None)
+                }
+                Action::RequestTakeback => {
+                    if !room_state.game.moves().iter().any(|m| m.color == color) {
+                        return Err(ReversiError::NoMoveToTakeBack);
+                    }
+                    // コンピュータはいつでも待ったを受け入れる
+                    let opponent = room_state.player(color.opposite()).map(|p| p.kind);
+                    if let Some(PlayerKind::Computer(_)) = opponent {
+                        room_state.take_back(color);
+                        ActionOutcome::Rewind
+                    } else {
+                        room_state.takeback_request = Some(color);
+                        ActionOutcome::Notify(
+                            ReversiMessageKind::TakebackRequest,
+                            ReversiMessageBody::TakebackRequest(color),
+                        )
+                    }
+                }
+                Action::AcceptTakeback => {
+                    if room_state.takeback_request != Some(color.opposite()) {
+                        return Err(ReversiError::NoTakebackRequest);
+                    }
+                    room_state.take_back(color.opposite());
+                    ActionOutcome::Rewind
+                }
+                Action::DeclineTakeback => {
+                    if room_state.takeback_request != Some(color.opposite()) {
+                        return Err(ReversiError::NoTakebackRequest);
+                    }
+                    room_state.takeback_request = None;
+                    ActionOutcome::Notify(
+                        ReversiMessageKind::TakebackDeclined,
+                        ReversiMessageBody::TakebackDeclined(color),
+                    )
                 }
+                Action::Rematch => {
+                    if !room_state.game.is_over {
+                        return Err(ReversiError::GameNotOver);
+                    }
+                    let opponent = room_state
+                        .player(color.opposite())
+                        .map(|p| (p.id, p.kind))
+                        .ok_or(ReversiError::OpponentLeft)?;
+                    // コンピュータはいつでも再戦を受ける
+                    match opponent {
+                        (opponent, kind)
+                            if !kind.is_computer()
+                                && room_state.rematch_offer != Some(opponent) =>
+                        {
+                            room_state.rematch_offer = Some(id);
+                            ActionOutcome::Notify(
+                                ReversiMessageKind::RematchOffer,
+                                ReversiMessageBody::RematchOffer(color),
+                            )
+                        }
+                        _ => {
+                            let (black_id, white_id) =
+                                room_state.restart().ok_or(ReversiError::OpponentLeft)?;
+                            ActionOutcome::Restart(black_id, white_id)
+                        }
+                    }
+                }
+            }
+        };
+
+        match outcome {
+            ActionOutcome::Finish(termination, winner) => {
+                self.finish_game(&room, termination, winner)
+            }
+            ActionOutcome::Notify(kind, body) => self.send_reversi_message_room(
+                &room,
+                ReversiMessage {
+                    id: None,
+                    kind,
+                    body: Some(body),
+                },
+                None,
+            ),
+            ActionOutcome::Rewind => {
+                self.update_lobby();
+                self.send_reversi_message_room(
+                    &room,
+                    ReversiMessage {
+                        id: None,
+                        kind: ReversiMessageKind::Game,
+                        body: Some(ReversiMessageBody::Game(self.rooms[&room].snapshot())),
+                    },
+                    None,
+                );
+                self.schedule_flag(&room, ctx);
+            }
+            ActionOutcome::Restart(black_id, white_id) => {
+                self.update_lobby();
+                self.send_game_start(&room, black_id, white_id);
+                self.schedule_flag(&room, ctx);
+                self.play_computer(&room, ctx);
             }
         }
+        Ok(())
     }
 }
 
@@ -413,47 +1785,561 @@ impl Handler<ListRooms> for GameServer {
     fn handle(&mut self, msg: ListRooms, _: &mut Context<Self>) -> Self::Result {
         let uid = msg.uid;
 
-        let room_list: Vec<(String, Room)> = self.rooms.clone().into_iter().collect();
-        println!("{:?}", room_list);
+        // 非公開の部屋は一覧に出さない
+        let mut room_list: Vec<(String, Room)> = self
+            .rooms
+            .iter()
+            .filter(|(_, room)| !room.private)
+            .map(|(name, room)| (name.clone(), room.clone()))
+            .collect();
+        for (_, room) in room_list.iter_mut() {
+            self.fill_ratings(room.player1.iter_mut().chain(room.player2.iter_mut()));
+        }
+        debug!("{:?}", room_list);
         MessageResult(room_list)
     }
 }
 
 impl Handler<Join> for GameServer {
-    type Result = ();
+    type Result = Result<(), ReversiError>;
+
+    fn handle(&mut self, msg: Join, ctx: &mut Context<Self>) -> Self::Result {
+        let Join {
+            name,
+            uid,
+            uname,
+            password,
+            code,
+        } = msg;
+        self.check_admission(&name, password.as_deref(), code.as_deref())?;
+        let (uname, kind) = self.identify(uid, uname)?;
+        let (black_id, white_id) = self.rooms.join(name.clone(), uid, uname, kind)?;
+        if let (Some(room), Some(code)) = (self.rooms.get_mut(&name), code) {
+            room.invites.remove(&code);
+        }
+        self.update_lobby();
+        self.send_scrollback(&self.rooms[&name].chat, uid);
+        self.send_game_start(&name, black_id, white_id);
+        self.schedule_flag(&name, ctx);
+        Ok(())
+    }
+}
+
+impl Handler<Watch> for GameServer {
+    type Result = Result<(), ReversiError>;
+
+    fn handle(&mut self, msg: Watch, _: &mut Context<Self>) -> Self::Result {
+        let Watch {
+            name,
+            uid,
+            password,
+            code,
+        } = msg;
+        self.check_admission(&name, password.as_deref(), code.as_deref())?;
+        self.rooms.watch(name.clone(), uid)?;
+        if let Some(code) = code {
+            self.rooms.get_mut(&name).unwrap().invites.remove(&code);
+        }
+        // 途中から観戦しても盤面が分かるように現在の局面を送る
+        let game = self.rooms[&name].snapshot();
+        self.send_reversi_message(
+            ReversiMessage {
+                id: None,
+                kind: ReversiMessageKind::Game,
+                body: Some(ReversiMessageBody::Game(game)),
+            },
+            uid,
+        );
+        self.send_scrollback(&self.rooms[&name].chat, uid);
+        Ok(())
+    }
+}
+
+impl Handler<Resume> for GameServer {
+    type Result = Result<String, ReversiError>;
+
+    fn handle(&mut self, msg: Resume, _: &mut Context<Self>) -> Self::Result {
+        let Resume { token, uid } = msg;
+        // トークンは一度しか使えない
+        let Seat {
+            room: room_name,
+            uid: old_uid,
+        } = self
+            .seats
+            .remove(&token)
+            .ok_or(ReversiError::InvalidResumeToken)?;
+
+        // 席を新しいセッションに付け替える
+        let room = match self.rooms.get_mut(&room_name) {
+            Some(room) => room,
+            None => return Err(ReversiError::NoSuchRoom(room_name)),
+        };
+        for player in room.player1.iter_mut().chain(room.player2.iter_mut()) {
+            if player.id == old_uid {
+                player.id = uid;
+            }
+        }
+        room.sessions.remove(&old_uid);
+        room.sessions.insert(uid);
+        info!("{}: Someone resumed", room_name);
 
-    fn handle(&mut self, msg: Join, _: &mut Context<Self>) {
-        let Join { name, uid, uname } = msg;
-        if let Ok((black_id, white_id)) = self.rooms.join(name, uid, uname) {
+        // 盤面と手番を送り直す
+        let color = room.color_of(uid);
+        let game = room.snapshot();
+        if let Some(color) = color {
             self.send_reversi_message(
                 ReversiMessage {
+                    id: None,
                     kind: ReversiMessageKind::GameStart,
-                    body: Some(ReversiMessageBody::GameStart(Color::Black)),
+                    body: Some(ReversiMessageBody::GameStart(color)),
+                },
+                uid,
+            );
+            // 次に切れたとき用の新しいトークン
+            let token = self.generate_token();
+            self.seats.insert(
+                token.clone(),
+                Seat {
+                    room: room_name.clone(),
+                    uid,
                 },
-                black_id,
             );
             self.send_reversi_message(
                 ReversiMessage {
-                    kind: ReversiMessageKind::GameStart,
-                    body: Some(ReversiMessageBody::GameStart(Color::White)),
+                    id: None,
+                    kind: ReversiMessageKind::ResumeToken,
+                    body: Some(ReversiMessageBody::ResumeToken(token)),
                 },
-                white_id,
+                uid,
             );
         }
+        self.send_reversi_message(
+            ReversiMessage {
+                id: None,
+                kind: ReversiMessageKind::Game,
+                body: Some(ReversiMessageBody::Game(game)),
+            },
+            uid,
+        );
+        self.send_scrollback(&self.rooms[&room_name].chat, uid);
+        Ok(room_name)
     }
 }
 
 impl Handler<MakeRoom> for GameServer {
-    type Result = ();
-    fn handle(&mut self, msg: MakeRoom, _: &mut Context<Self>) {
+    type Result = Result<(), ReversiError>;
+    fn handle(&mut self, msg: MakeRoom, ctx: &mut Context<Self>) -> Self::Result {
         let MakeRoom {
             name,
             uid,
             uname,
             color,
+            opponent,
+            time_control,
+            private,
+            password,
         } = msg;
 
-        println!("{} made GameRoom: {}", uname, name);
-        self.rooms.make_room(name, uid, uname, color);
+        let (uname, kind) = self.identify(uid, uname)?;
+        self.rooms.make_room(
+            name.clone(),
+            uid,
+            uname.clone(),
+            kind,
+            color,
+            time_control,
+            self.max_rooms,
+        )?;
+        info!("{} made GameRoom: {}", uname, name);
+        let room = self.rooms.get_mut(&name).unwrap();
+        room.private = private;
+        room.locked = password.is_some();
+        room.password = password;
+        self.update_lobby();
+
+        if let Some(level) = opponent {
+            let cpu_id = self.rng.gen::<usize>();
+            let cpu_name = format!("CPU (level {})", level);
+            let kind = PlayerKind::Computer(level);
+            let (black_id, white_id) = self.rooms.join(name.clone(), cpu_id, cpu_name, kind)?;
+            self.update_lobby();
+            self.send_game_start(&name, black_id, white_id);
+            self.schedule_flag(&name, ctx);
+            self.play_computer(&name, ctx);
+        }
+        Ok(())
+    }
+}
+
+impl Handler<Queue> for GameServer {
+    type Result = Result<(), ReversiError>;
+
+    fn handle(&mut self, msg: Queue, ctx: &mut Context<Self>) -> Self::Result {
+        let (uname, kind) = self.identify(msg.uid, msg.uname)?;
+        let entry = QueueEntry {
+            rating: self.rating(&uname).rating,
+            uid: msg.uid,
+            uname,
+            kind,
+            time_control: msg.time_control,
+            band: msg.band,
+        };
+        // 並び直すときは前の条件を取り消す
+        if let Some(i) = self.queue.iter().position(|e| e.uid == entry.uid) {
+            let previous = self.queue.remove(i);
+            self.send_queue_status(previous.time_control);
+        }
+
+        let time_control = entry.time_control;
+        self.queue.push(entry);
+        self.match_queue(ctx);
+        self.send_queue_status(time_control);
+        Ok(())
+    }
+}
+
+impl Handler<Unqueue> for GameServer {
+    type Result = Result<(), ReversiError>;
+
+    fn handle(&mut self, msg: Unqueue, _: &mut Context<Self>) -> Self::Result {
+        let i = self
+            .queue
+            .iter()
+            .position(|e| e.uid == msg.uid)
+            .ok_or(ReversiError::NotQueued)?;
+        let entry = self.queue.remove(i);
+        self.send_reversi_message(
+            ReversiMessage {
+                id: None,
+                kind: ReversiMessageKind::QueueStatus,
+                body: Some(ReversiMessageBody::QueueStatus(QueueStatus {
+                    queued: false,
+                    time_control: entry.time_control,
+                    waiting: 0,
+                })),
+            },
+            msg.uid,
+        );
+        self.send_queue_status(entry.time_control);
+        Ok(())
+    }
+}
+
+impl Handler<Leaderboard> for GameServer {
+    type Result = Result<Vec<Rating>, ReversiError>;
+
+    fn handle(&mut self, msg: Leaderboard, _: &mut Context<Self>) -> Self::Result {
+        self.archive.leaderboard(msg.limit).map_err(|e| {
+            error!("Failed to read the leaderboard: {}", e);
+            ReversiError::Storage
+        })
+    }
+}
+
+impl Handler<RoomSummaries> for GameServer {
+    type Result = MessageResult<RoomSummaries>;
+
+    fn handle(&mut self, _: RoomSummaries, _: &mut Context<Self>) -> Self::Result {
+        let mut summaries: Vec<RoomSummary> = self
+            .rooms
+            .iter()
+            .filter(|(_, room)| !room.private)
+            .map(|(name, room)| room.summary(name))
+            .collect();
+        summaries.sort_by(|a, b| a.name.cmp(&b.name));
+        MessageResult(summaries)
+    }
+}
+
+impl Handler<RoomDetails> for GameServer {
+    type Result = Result<RoomDetail, ReversiError>;
+
+    fn handle(&mut self, msg: RoomDetails, _: &mut Context<Self>) -> Self::Result {
+        // 非公開の部屋は存在しないものとして扱う
+        let mut detail = match self.rooms.get(&msg.name) {
+            Some(room) if !room.private => room.detail(&msg.name),
+            _ => return Err(ReversiError::NoSuchRoom(msg.name)),
+        };
+        self.fill_ratings(detail.player1.iter_mut().chain(detail.player2.iter_mut()));
+        Ok(detail)
+    }
+}
+
+impl Handler<GetGame> for GameServer {
+    type Result = Result<Option<ArchivedGame>, StorageError>;
+
+    fn handle(&mut self, msg: GetGame, _: &mut Context<Self>) -> Self::Result {
+        self.archive.get(msg.id)
+    }
+}
+
+impl Handler<FindGames> for GameServer {
+    type Result = Result<Vec<ArchivedGame>, ReversiError>;
+
+    fn handle(&mut self, msg: FindGames, _: &mut Context<Self>) -> Self::Result {
+        let from = msg.from.unwrap_or(0);
+        let to = msg.to.unwrap_or(Timestamp::MAX);
+        let games = match msg.player {
+            Some(ref player) => self.archive.by_player(player).map(|games| {
+                games
+                    .into_iter()
+                    .filter(|game| from <= game.finished_at && game.finished_at <= to)
+                    .collect()
+            }),
+            None if msg.from.is_some() || msg.to.is_some() => self.archive.between(from, to),
+            // 全件は返さない
+            None => {
+                return Err(ReversiError::InvalidRequest(
+                    "player, from or to is required".to_owned(),
+                ))
+            }
+        };
+        games.map_err(|e| {
+            error!("Failed to look up games: {}", e);
+            ReversiError::Storage
+        })
+    }
+}
+
+impl Handler<Analyze> for GameServer {
+    type Result = ResponseActFuture<Self, Analysis, ReversiError>;
+
+    fn handle(&mut self, msg: Analyze, _: &mut Context<Self>) -> Self::Result {
+        let level = msg.level.unwrap_or_default();
+        if level.0 > Level::MAX.0 {
+            return Box::new(fut::err(ReversiError::InvalidRequest(format!(
+                "Level must be between 0 and {}",
+                Level::MAX
+            ))));
+        }
+        let game = match ReversiGame::from_position_string(&msg.position) {
+            Ok(game) => game,
+            Err(e) => return Box::new(fut::err(ReversiError::InvalidRequest(e.to_string()))),
+        };
+        // 誰でも呼べるので、同時に走らせる数を抑える
+        if self.pending_analyses >= MAX_PENDING_ANALYSES {
+            return Box::new(fut::err(ReversiError::Busy));
+        }
+        self.pending_analyses += 1;
+        let analysis = self
+            .analyzer
+            .send(AnalyzePosition { game, level })
+            .into_actor(self)
+            .then(|res, act, _| {
+                act.pending_analyses -= 1;
+                match res {
+                    Ok(analysis) => fut::ok(analysis),
+                    Err(e) => {
+                        error!("Failed to analyze a position: {}", e);
+                        fut::err(ReversiError::Busy)
+                    }
+                }
+            });
+        Box::new(analysis)
+    }
+}
+
+impl Handler<Register> for GameServer {
+    type Result = ResponseActFuture<Self, Token, ReversiError>;
+
+    fn handle(&mut self, msg: Register, _: &mut Context<Self>) -> Self::Result {
+        if let Err(e) = msg.credentials.validate() {
+            return Box::new(fut::err(ReversiError::InvalidRequest(e)));
+        }
+        let Credentials { name, password } = msg.credentials;
+        match self.account(&name) {
+            Ok(None) => {}
+            Ok(Some(_)) => return Box::new(fut::err(ReversiError::NameTaken(name))),
+            Err(e) => return Box::new(fut::err(e)),
+        }
+        // ハッシュの計算は重いので別スレッドで行う
+        let hashed = self
+            .passwords
+            .send(HashPassword { password })
+            .into_actor(self)
+            .then(move |res, act, _| {
+                let password_hash = match res {
+                    Ok(Ok(password_hash)) => password_hash,
+                    Ok(Err(e)) => {
+                        error!("Failed to hash the password of {}: {}", name, e);
+                        return fut::err(ReversiError::InvalidCredentials);
+                    }
+                    Err(e) => {
+                        error!("Failed to hash the password of {}: {}", name, e);
+                        return fut::err(ReversiError::InvalidCredentials);
+                    }
+                };
+                // 計算している間に同じ名前で登録されているかもしれない
+                match act.account(&name) {
+                    Ok(None) => {}
+                    Ok(Some(_)) => return fut::err(ReversiError::NameTaken(name)),
+                    Err(e) => return fut::err(e),
+                }
+                let account = Account {
+                    name: name.clone(),
+                    password_hash,
+                    created_at: storage::now(),
+                };
+                if let Err(e) = act.archive.create_account(&account) {
+                    error!("Failed to register {}: {}", name, e);
+                    return fut::err(ReversiError::Storage);
+                }
+                info!("{} registered", name);
+                fut::ok(act.log_in(name))
+            });
+        Box::new(hashed)
+    }
+}
+
+impl Handler<Login> for GameServer {
+    type Result = ResponseActFuture<Self, Token, ReversiError>;
+
+    fn handle(&mut self, msg: Login, _: &mut Context<Self>) -> Self::Result {
+        let Credentials { name, password } = msg.credentials;
+        let password_hash = match self.account(&name) {
+            Ok(Some(account)) => account.password_hash,
+            Ok(None) => return Box::new(fut::err(ReversiError::InvalidCredentials)),
+            Err(e) => return Box::new(fut::err(e)),
+        };
+        let verified = self
+            .passwords
+            .send(VerifyPassword {
+                password,
+                password_hash,
+            })
+            .into_actor(self)
+            .then(move |res, act, _| match res {
+                Ok(true) => fut::ok(act.log_in(name)),
+                _ => fut::err(ReversiError::InvalidCredentials),
+            });
+        Box::new(verified)
+    }
+}
+
+impl Handler<Logout> for GameServer {
+    type Result = Result<(), ReversiError>;
+
+    fn handle(&mut self, msg: Logout, _: &mut Context<Self>) -> Self::Result {
+        self.logins
+            .remove(&msg.token)
+            .map(|_| ())
+            .ok_or(ReversiError::InvalidSessionToken)
+    }
+}
+
+impl Handler<Authenticate> for GameServer {
+    type Result = Result<Uname, ReversiError>;
+
+    fn handle(&mut self, msg: Authenticate, _: &mut Context<Self>) -> Self::Result {
+        let name = match self.logins.get(&msg.token) {
+            Some(login) if login.expires > Instant::now() => login.name.clone(),
+            _ => return Err(ReversiError::InvalidSessionToken),
+        };
+        self.users.insert(msg.uid, name.clone());
+        Ok(name)
+    }
+}
+
+impl Handler<Invite> for GameServer {
+    type Result = Result<String, ReversiError>;
+
+    fn handle(&mut self, msg: Invite, _: &mut Context<Self>) -> Self::Result {
+        let code = self.generate_token();
+        let room = self
+            .rooms
+            .get_mut(&msg.room)
+            .ok_or_else(|| ReversiError::NoSuchRoom(msg.room.clone()))?;
+        if !room.is_player(msg.uid) {
+            return Err(ReversiError::NotAPlayer);
+        }
+        room.invites.insert(code.clone());
+        Ok(code)
+    }
+}
+
+impl Handler<SubscribeLobby> for GameServer {
+    type Result = Result<(), ReversiError>;
+
+    fn handle(&mut self, msg: SubscribeLobby, _: &mut Context<Self>) -> Self::Result {
+        self.update_lobby();
+        self.lobby.insert(msg.uid);
+        self.send_scrollback(&self.lobby_chat, msg.uid);
+        // まず今ある部屋をすべて送る
+        for summary in self.lobby_rooms.values() {
+            self.send_reversi_message(
+                ReversiMessage {
+                    id: None,
+                    kind: ReversiMessageKind::RoomCreated,
+                    body: Some(ReversiMessageBody::RoomCreated(summary.clone())),
+                },
+                msg.uid,
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Handler<UnsubscribeLobby> for GameServer {
+    type Result = Result<(), ReversiError>;
+
+    fn handle(&mut self, msg: UnsubscribeLobby, _: &mut Context<Self>) -> Self::Result {
+        self.lobby.remove(&msg.uid);
+        Ok(())
+    }
+}
+
+impl Handler<Chat> for GameServer {
+    type Result = Result<(), ReversiError>;
+
+    fn handle(&mut self, msg: Chat, _: &mut Context<Self>) -> Self::Result {
+        let text = msg.text.trim();
+        if text.is_empty() {
+            return Ok(());
+        }
+        if text.chars().count() > MAX_CHAT_LEN {
+            return Err(ReversiError::ChatTooLong);
+        }
+        let room = match msg.channel {
+            ChatChannel::Lobby => None,
+            ChatChannel::Room(ref name) => {
+                let room = self
+                    .rooms
+                    .get(name)
+                    .ok_or_else(|| ReversiError::NoSuchRoom(name.clone()))?;
+                if !room.sessions.contains(&msg.uid) {
+                    return Err(ReversiError::NotInRoom);
+                }
+                Some((name.clone(), room))
+            }
+        };
+        let chat = ChatMessage {
+            from: self.chat_name(msg.uid, room.as_ref().map(|(_, room)| *room)),
+            room: room.map(|(name, _)| name),
+            text: text.to_owned(),
+            sent_at: storage::now(),
+        };
+        let message = ReversiMessage {
+            id: None,
+            kind: ReversiMessageKind::Chat,
+            body: Some(ReversiMessageBody::Chat(chat.clone())),
+        };
+
+        let scrollback = match chat.room {
+            Some(ref name) => {
+                self.send_reversi_message_room(name, message, None);
+                &mut self.rooms.get_mut(name).unwrap().chat
+            }
+            None => {
+                for &uid in &self.lobby {
+                    self.send_reversi_message(message.clone(), uid);
+                }
+                &mut self.lobby_chat
+            }
+        };
+        scrollback.push_back(chat);
+        if scrollback.len() > CHAT_SCROLLBACK {
+            scrollback.pop_front();
+        }
+        Ok(())
     }
 }
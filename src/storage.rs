@@ -0,0 +1,536 @@
+use account::Account;
+use rating::Rating;
+use reversi::board::Color;
+use rusqlite::{params, Connection, Row};
+use std::error;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub type GameId = i64;
+
+/// Seconds since the Unix epoch
+pub type Timestamp = u64;
+
+pub fn now() -> Timestamp {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// How a game ended.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Termination {
+    /// Neither player could move
+    Normal,
+    /// A player left before the end
+    Abandoned,
+    /// A player ran out of time
+    Timeout,
+    Resigned,
+    DrawAgreed,
+    /// Called off before the first move
+    Aborted,
+}
+
+impl Termination {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Termination::Normal => "Normal",
+            Termination::Abandoned => "Abandoned",
+            Termination::Timeout => "Timeout",
+            Termination::Resigned => "Resigned",
+            Termination::DrawAgreed => "DrawAgreed",
+            Termination::Aborted => "Aborted",
+        }
+    }
+}
+
+impl FromStr for Termination {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Normal" => Ok(Termination::Normal),
+            "Abandoned" => Ok(Termination::Abandoned),
+            "Timeout" => Ok(Termination::Timeout),
+            "Resigned" => Ok(Termination::Resigned),
+            "DrawAgreed" => Ok(Termination::DrawAgreed),
+            "Aborted" => Ok(Termination::Aborted),
+            _ => Err(format!("Invalid Termination: {}", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ArchivedGame {
+    /// Assigned by the archive when the game is stored
+    pub id: Option<GameId>,
+    pub room: String,
+    pub black: String,
+    pub white: String,
+    /// Transcript of the game, see `Game::to_transcript`
+    pub moves: String,
+    pub black_discs: u32,
+    pub white_discs: u32,
+    pub winner: Option<Color>,
+    pub termination: Termination,
+    pub started_at: Timestamp,
+    pub finished_at: Timestamp,
+}
+
+#[derive(Debug)]
+pub struct StorageError(pub String);
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "storage error: {}", self.0)
+    }
+}
+
+impl error::Error for StorageError {}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self {
+        StorageError(e.to_string())
+    }
+}
+
+/// Where finished games are kept.
+pub trait GameArchive {
+    fn store(&mut self, game: &ArchivedGame) -> Result<GameId, StorageError>;
+    fn get(&self, id: GameId) -> Result<Option<ArchivedGame>, StorageError>;
+    /// Games in which `name` played either color, oldest first
+    fn by_player(&self, name: &str) -> Result<Vec<ArchivedGame>, StorageError>;
+    /// Games finished between `from` and `to` inclusive, oldest first
+    fn between(&self, from: Timestamp, to: Timestamp) -> Result<Vec<ArchivedGame>, StorageError>;
+}
+
+/// Where player ratings are kept.
+pub trait RatingStore {
+    fn rating(&self, name: &str) -> Result<Option<Rating>, StorageError>;
+    fn store_rating(&mut self, rating: &Rating) -> Result<(), StorageError>;
+    /// The `limit` best rated players, best first
+    fn leaderboard(&self, limit: usize) -> Result<Vec<Rating>, StorageError>;
+}
+
+/// Where registered players are kept.
+pub trait AccountStore {
+    /// Fails if the name is taken
+    fn create_account(&mut self, account: &Account) -> Result<(), StorageError>;
+    fn account(&self, name: &str) -> Result<Option<Account>, StorageError>;
+}
+
+/// Everything the game server persists
+pub trait Storage: GameArchive + RatingStore + AccountStore {}
+
+impl<T: GameArchive + RatingStore + AccountStore> Storage for T {}
+
+/// Keeps games for the lifetime of the process. Used in tests.
+#[derive(Default)]
+pub struct MemoryArchive {
+    games: Vec<ArchivedGame>,
+    ratings: Vec<Rating>,
+    accounts: Vec<Account>,
+}
+
+impl MemoryArchive {
+    pub fn new() -> Self {
+        MemoryArchive::default()
+    }
+}
+
+impl GameArchive for MemoryArchive {
+    fn store(&mut self, game: &ArchivedGame) -> Result<GameId, StorageError> {
+        let id = self.games.len() as GameId + 1;
+        self.games.push(ArchivedGame {
+            id: Some(id),
+            ..game.clone()
+        });
+        Ok(id)
+    }
+
+    fn get(&self, id: GameId) -> Result<Option<ArchivedGame>, StorageError> {
+        Ok(self.games.iter().find(|g| g.id == Some(id)).cloned())
+    }
+
+    fn by_player(&self, name: &str) -> Result<Vec<ArchivedGame>, StorageError> {
+        let mut games: Vec<ArchivedGame> = self
+            .games
+            .iter()
+            .filter(|g| g.black == name || g.white == name)
+            .cloned()
+            .collect();
+        games.sort_by_key(|g| g.finished_at);
+        Ok(games)
+    }
+
+    fn between(&self, from: Timestamp, to: Timestamp) -> Result<Vec<ArchivedGame>, StorageError> {
+        let mut games: Vec<ArchivedGame> = self
+            .games
+            .iter()
+            .filter(|g| from <= g.finished_at && g.finished_at <= to)
+            .cloned()
+            .collect();
+        games.sort_by_key(|g| g.finished_at);
+        Ok(games)
+    }
+}
+
+impl RatingStore for MemoryArchive {
+    fn rating(&self, name: &str) -> Result<Option<Rating>, StorageError> {
+        Ok(self.ratings.iter().find(|r| r.name == name).cloned())
+    }
+
+    fn store_rating(&mut self, rating: &Rating) -> Result<(), StorageError> {
+        self.ratings.retain(|r| r.name != rating.name);
+        self.ratings.push(rating.clone());
+        Ok(())
+    }
+
+    fn leaderboard(&self, limit: usize) -> Result<Vec<Rating>, StorageError> {
+        let mut ratings = self.ratings.clone();
+        ratings.sort_by(|a, b| b.rating.partial_cmp(&a.rating).unwrap());
+        ratings.truncate(limit);
+        Ok(ratings)
+    }
+}
+
+impl AccountStore for MemoryArchive {
+    fn create_account(&mut self, account: &Account) -> Result<(), StorageError> {
+        if self.accounts.iter().any(|a| a.name == account.name) {
+            return Err(StorageError(format!(
+                "{} is already registered",
+                account.name
+            )));
+        }
+        self.accounts.push(account.clone());
+        Ok(())
+    }
+
+    fn account(&self, name: &str) -> Result<Option<Account>, StorageError> {
+        Ok(self.accounts.iter().find(|a| a.name == name).cloned())
+    }
+}
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS games (
+        id          INTEGER PRIMARY KEY AUTOINCREMENT,
+        room        TEXT NOT NULL,
+        black       TEXT NOT NULL,
+        white       TEXT NOT NULL,
+        moves       TEXT NOT NULL,
+        black_discs INTEGER NOT NULL,
+        white_discs INTEGER NOT NULL,
+        winner      TEXT,
+        termination TEXT NOT NULL,
+        started_at  INTEGER NOT NULL,
+        finished_at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS games_black ON games (black);
+    CREATE INDEX IF NOT EXISTS games_white ON games (white);
+    CREATE INDEX IF NOT EXISTS games_finished_at ON games (finished_at);
+    CREATE TABLE IF NOT EXISTS ratings (
+        name   TEXT PRIMARY KEY,
+        rating REAL NOT NULL,
+        games  INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS ratings_rating ON ratings (rating);
+    CREATE TABLE IF NOT EXISTS accounts (
+        name          TEXT PRIMARY KEY,
+        password_hash TEXT NOT NULL,
+        created_at    INTEGER NOT NULL
+    );
+";
+
+const COLUMNS: &str = "id, room, black, white, moves, black_discs, white_discs, winner, \
+                       termination, started_at, finished_at";
+
+pub struct SqliteArchive {
+    conn: Connection,
+}
+
+impl SqliteArchive {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
+        SqliteArchive::with_connection(Connection::open(path)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self, StorageError> {
+        SqliteArchive::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self, StorageError> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(SqliteArchive { conn })
+    }
+
+    fn query<P: rusqlite::Params>(
+        &self,
+        filter: &str,
+        params: P,
+    ) -> Result<Vec<ArchivedGame>, StorageError> {
+        let sql = format!(
+            "SELECT {} FROM games WHERE {} ORDER BY finished_at, id",
+            COLUMNS, filter
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let games = stmt
+            .query_map(params, from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(games)
+    }
+}
+
+/// Fails on a column that does not hold a value this module wrote.
+fn parse_column<T: FromStr<Err = String>>(index: usize, s: &str) -> rusqlite::Result<T> {
+    s.parse().map_err(|e: String| {
+        rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, e.into())
+    })
+}
+
+fn from_row(row: &Row) -> rusqlite::Result<ArchivedGame> {
+    let winner: Option<String> = row.get(7)?;
+    let termination: String = row.get(8)?;
+    let started_at: i64 = row.get(9)?;
+    let finished_at: i64 = row.get(10)?;
+    Ok(ArchivedGame {
+        id: Some(row.get(0)?),
+        room: row.get(1)?,
+        black: row.get(2)?,
+        white: row.get(3)?,
+        moves: row.get(4)?,
+        black_discs: row.get(5)?,
+        white_discs: row.get(6)?,
+        winner: winner.map(|w| parse_column::<Color>(7, &w)).transpose()?,
+        termination: parse_column(8, &termination)?,
+        started_at: started_at as Timestamp,
+        finished_at: finished_at as Timestamp,
+    })
+}
+
+impl GameArchive for SqliteArchive {
+    fn store(&mut self, game: &ArchivedGame) -> Result<GameId, StorageError> {
+        self.conn.execute(
+            "INSERT INTO games (room, black, white, moves, black_discs, white_discs, winner, \
+             termination, started_at, finished_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                game.room,
+                game.black,
+                game.white,
+                game.moves,
+                game.black_discs,
+                game.white_discs,
+                game.winner.map(|c| format!("{:?}", c)),
+                game.termination.as_str(),
+                game.started_at as i64,
+                game.finished_at as i64,
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    fn get(&self, id: GameId) -> Result<Option<ArchivedGame>, StorageError> {
+        Ok(self.query("id = ?1", params![id])?.pop())
+    }
+
+    fn by_player(&self, name: &str) -> Result<Vec<ArchivedGame>, StorageError> {
+        self.query("black = ?1 OR white = ?1", params![name])
+    }
+
+    fn between(&self, from: Timestamp, to: Timestamp) -> Result<Vec<ArchivedGame>, StorageError> {
+        self.query(
+            "finished_at BETWEEN ?1 AND ?2",
+            params![from as i64, to as i64],
+        )
+    }
+}
+
+fn rating_from_row(row: &Row) -> rusqlite::Result<Rating> {
+    Ok(Rating {
+        name: row.get(0)?,
+        rating: row.get(1)?,
+        games: row.get(2)?,
+    })
+}
+
+impl RatingStore for SqliteArchive {
+    fn rating(&self, name: &str) -> Result<Option<Rating>, StorageError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, rating, games FROM ratings WHERE name = ?1")?;
+        let rating = stmt
+            .query_map(params![name], rating_from_row)?
+            .next()
+            .transpose()?;
+        Ok(rating)
+    }
+
+    fn store_rating(&mut self, rating: &Rating) -> Result<(), StorageError> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO ratings (name, rating, games) VALUES (?1, ?2, ?3)",
+            params![rating.name, rating.rating, rating.games],
+        )?;
+        Ok(())
+    }
+
+    fn leaderboard(&self, limit: usize) -> Result<Vec<Rating>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT name, rating, games FROM ratings ORDER BY rating DESC, name LIMIT ?1",
+        )?;
+        let ratings = stmt
+            .query_map(params![limit as i64], rating_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ratings)
+    }
+}
+
+impl AccountStore for SqliteArchive {
+    fn create_account(&mut self, account: &Account) -> Result<(), StorageError> {
+        self.conn.execute(
+            "INSERT INTO accounts (name, password_hash, created_at) VALUES (?1, ?2, ?3)",
+            params![
+                account.name,
+                account.password_hash,
+                account.created_at as i64
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn account(&self, name: &str) -> Result<Option<Account>, StorageError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, password_hash, created_at FROM accounts WHERE name = ?1")?;
+        let account = stmt
+            .query_map(params![name], |row| {
+                let created_at: i64 = row.get(2)?;
+                Ok(Account {
+                    name: row.get(0)?,
+                    password_hash: row.get(1)?,
+                    created_at: created_at as Timestamp,
+                })
+            })?
+            .next()
+            .transpose()?;
+        Ok(account)
+    }
+}
+
+#[test]
+fn test_archives() {
+    fn check(archive: &mut dyn GameArchive) {
+        let game = |black: &str, white: &str, finished_at| ArchivedGame {
+            id: None,
+            room: "Shiba".to_owned(),
+            black: black.to_owned(),
+            white: white.to_owned(),
+            moves: "f5d6c3d3c4".to_owned(),
+            black_discs: 40,
+            white_discs: 24,
+            winner: Some(Color::Black),
+            termination: Termination::Normal,
+            started_at: finished_at - 600,
+            finished_at,
+        };
+        let id = archive.store(&game("pipopa", "Tatsuo", 2000)).unwrap();
+        archive.store(&game("Tatsuo", "hikotq", 1000)).unwrap();
+        archive
+            .store(&ArchivedGame {
+                winner: None,
+                termination: Termination::Abandoned,
+                ..game("hikotq", "pipopa", 3000)
+            })
+            .unwrap();
+
+        let stored = archive.get(id).unwrap().unwrap();
+        assert_eq!(
+            stored,
+            ArchivedGame {
+                id: Some(id),
+                ..game("pipopa", "Tatsuo", 2000)
+            }
+        );
+        assert!(archive.get(id + 100).unwrap().is_none());
+
+        let tatsuo = archive.by_player("Tatsuo").unwrap();
+        assert_eq!(tatsuo.len(), 2);
+        assert_eq!(tatsuo[0].finished_at, 1000);
+        let pipopa = archive.by_player("pipopa").unwrap();
+        assert_eq!(pipopa[1].termination, Termination::Abandoned);
+        assert_eq!(pipopa[1].winner, None);
+
+        let range = archive.between(1500, 3000).unwrap();
+        assert_eq!(range.len(), 2);
+        assert_eq!(range[0].black, "pipopa");
+    }
+
+    check(&mut MemoryArchive::new());
+    let mut archive = SqliteArchive::open_in_memory().unwrap();
+    check(&mut archive);
+
+    // a result this version cannot read is an error, not a normal game
+    archive
+        .conn
+        .execute("UPDATE games SET termination = 'Exploded' WHERE id = 1", [])
+        .unwrap();
+    assert!(archive.get(1).is_err());
+    assert!(archive.get(2).unwrap().is_some());
+}
+
+#[test]
+fn test_ratings() {
+    fn check(store: &mut dyn RatingStore) {
+        let rating = |name: &str, rating, games| Rating {
+            name: name.to_owned(),
+            rating,
+            games,
+        };
+        store.store_rating(&rating("pipopa", 1484.0, 1)).unwrap();
+        store.store_rating(&rating("Tatsuo", 1516.0, 1)).unwrap();
+        store.store_rating(&rating("hikotq", 1500.0, 0)).unwrap();
+        store.store_rating(&rating("pipopa", 1530.5, 2)).unwrap();
+
+        assert_eq!(
+            store.rating("pipopa").unwrap(),
+            Some(rating("pipopa", 1530.5, 2))
+        );
+        assert_eq!(store.rating("nobody").unwrap(), None);
+        let names: Vec<String> = store
+            .leaderboard(2)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.name)
+            .collect();
+        assert_eq!(names, vec!["pipopa", "Tatsuo"]);
+    }
+
+    check(&mut MemoryArchive::new());
+    check(&mut SqliteArchive::open_in_memory().unwrap());
+}
+
+#[test]
+fn test_accounts() {
+    fn check(store: &mut dyn AccountStore) {
+        let account = Account {
+            name: "pipopa".to_owned(),
+            password_hash: "$argon2id$v=19$...".to_owned(),
+            created_at: 1000,
+        };
+        store.create_account(&account).unwrap();
+        assert!(store
+            .create_account(&Account {
+                password_hash: "$argon2id$v=19$other".to_owned(),
+                ..account.clone()
+            })
+            .is_err());
+        assert_eq!(store.account("pipopa").unwrap(), Some(account));
+        assert_eq!(store.account("Tatsuo").unwrap(), None);
+    }
+
+    check(&mut MemoryArchive::new());
+    check(&mut SqliteArchive::open_in_memory().unwrap());
+}
@@ -0,0 +1,100 @@
+use account;
+use actix::prelude::*;
+use rand;
+use reversi::ai::{self, Analysis, Level};
+use reversi::board::Move;
+use reversi::game::Game;
+
+/// Threads searching positions for computer players
+pub const WORKER_THREADS: usize = 2;
+/// Threads analysing positions for `/api/analyze`
+pub const ANALYSIS_THREADS: usize = 1;
+/// Threads hashing and checking passwords
+pub const PASSWORD_THREADS: usize = 2;
+
+/// Runs engine searches on threads of their own, so that a computer
+/// thinking does not hold up every other room on the `GameServer`.
+pub struct EngineWorker;
+
+impl Actor for EngineWorker {
+    type Context = SyncContext<Self>;
+}
+
+/// Pick a move for the side to move, `None` if the game is over
+pub struct ChooseMove {
+    pub game: Game,
+    pub level: Level,
+}
+
+impl actix::Message for ChooseMove {
+    type Result = Option<Move>;
+}
+
+impl Handler<ChooseMove> for EngineWorker {
+    type Result = Option<Move>;
+
+    fn handle(&mut self, msg: ChooseMove, _: &mut Self::Context) -> Self::Result {
+        ai::choose_move(&msg.game, msg.level, &mut rand::thread_rng())
+    }
+}
+
+/// Search a position for `/api/analyze`
+pub struct AnalyzePosition {
+    pub game: Game,
+    pub level: Level,
+}
+
+impl actix::Message for AnalyzePosition {
+    type Result = Analysis;
+}
+
+impl Handler<AnalyzePosition> for EngineWorker {
+    type Result = MessageResult<AnalyzePosition>;
+
+    fn handle(&mut self, msg: AnalyzePosition, _: &mut Self::Context) -> Self::Result {
+        MessageResult(ai::analyze(&msg.game, msg.level))
+    }
+}
+
+/// Hashes and checks passwords, which argon2 makes slow on purpose, away
+/// from the `GameServer`.
+pub struct PasswordWorker;
+
+impl Actor for PasswordWorker {
+    type Context = SyncContext<Self>;
+}
+
+/// Hash a password for a new account
+pub struct HashPassword {
+    pub password: String,
+}
+
+impl actix::Message for HashPassword {
+    type Result = Result<String, String>;
+}
+
+impl Handler<HashPassword> for PasswordWorker {
+    type Result = Result<String, String>;
+
+    fn handle(&mut self, msg: HashPassword, _: &mut Self::Context) -> Self::Result {
+        account::hash_password(&msg.password)
+    }
+}
+
+/// Check a password against the hash stored for an account
+pub struct VerifyPassword {
+    pub password: String,
+    pub password_hash: String,
+}
+
+impl actix::Message for VerifyPassword {
+    type Result = bool;
+}
+
+impl Handler<VerifyPassword> for PasswordWorker {
+    type Result = bool;
+
+    fn handle(&mut self, msg: VerifyPassword, _: &mut Self::Context) -> Self::Result {
+        account::verify_password(&msg.password, &msg.password_hash)
+    }
+}
@@ -51,6 +51,8 @@ var vm = new Vue({
       turn: null, 
       ownColor: null, 
       conn: null, 
+      resumeToken: null, 
+      clock: null, 
     };
   },
   methods: {
@@ -69,6 +71,12 @@ var vm = new Vue({
     canPut: function(x, y) {
       return this.board[y][x] == AVAILABLE;
     }, 
+    formatTime: function(ms) {
+      let seconds = Math.floor(ms / 1000);
+      let minutes = Math.floor(seconds / 60);
+      seconds = seconds % 60;
+      return minutes + ":" + (seconds < 10 ? "0" : "") + seconds;
+    }, 
     oppositeColor: function(color) {
       if(color.toLowerCase() == 'black') {
         return WHITE;
@@ -78,6 +86,7 @@ var vm = new Vue({
     disconnect: function() {
       if (this.conn != null) {
         console.log('Disconnecting...');
+        this.conn.onclose = null;
         this.conn.close();
         this.conn = null;
       }
@@ -107,6 +116,7 @@ var vm = new Vue({
           case 'Game':
             board = mBody.Game.board;
             that.turn = mBody.Game.turn.toLowerCase();
+            that.clock = mBody.Game.clock || null;
             for (var y = 0; y < 8; y++) {
               for (var x = 0; x < 8; x++) {
                 that.board[y].splice(x, 1, board[y * 8 + x]);
@@ -118,12 +128,21 @@ var vm = new Vue({
             board = game.board;
             let winner = mBody.GameOver[1];
             that.turn = game.turn.toLowerCase();
+            that.clock = game.clock || null;
             for (var y = 0; y < 8; y++) {
               for (var x = 0; x < 8; x++) {
                 that.board[y].splice(x, 1, board[y * 8 + x]);
               }
             }
+            that.resumeToken = null;
             swal("Game is over!", winner + " is  winner!");
+            break;
+          case 'ResumeToken':
+            that.resumeToken = mBody.ResumeToken;
+            break;
+          case 'Invite':
+            swal("Send this link to your friend", window.location.origin + mBody.Invite.url);
+            break;
           default:
             break;
         }
@@ -132,28 +151,64 @@ var vm = new Vue({
       that.conn.onclose = function() {
         console.log('Disconnected.');
         that.conn = null;
+        // try to take our seat back while the server holds it
+        if (that.resumeToken != null) {
+          setTimeout(function() { that.resume(); }, 1000);
+        }
       };
     }, 
+    resume: function() {
+      let that = this;
+      let cmd = ["/resume", that.resumeToken].join(' ');
+      this.connect(
+        function() {
+          that.conn.send(cmd);
+        }
+      );
+    }, 
     send_move: function(color, x, y) {
       cmd = ["/move", color, x, y].join(' ');
       this.conn.send(cmd);
     }, 
-    join(channel, uname) {
+    join(channel, uname, code) {
       let that = this;
-      let cmd = ["/join", channel, uname].join(' ');
+      let cmd_array = ["/join", channel, uname];
+      // invite links carry a one-time code
+      if(code != null) {
+        cmd_array.push("code:" + code);
+      }
+      let cmd = cmd_array.join(' ');
       this.connect(
         function() {
           that.conn.send(cmd);
         }
       );
     }, 
-    makeRoom: function(channel, uname, color) {
+    watch(channel) {
+      let that = this;
+      let cmd = ["/watch", channel].join(' ');
+      that.ownColor = null;
+      this.connect(
+        function() {
+          that.conn.send(cmd);
+        }
+      );
+    }, 
+    invite: function() {
+      if (this.conn != null) {
+        this.conn.send("/invite");
+      }
+    }, 
+    makeRoom: function(channel, uname, color, cpuLevel) {
       let that = this;
       let cmd_array = ["/makeRoom", channel, uname];
       if(color != null) {
         that.color = color;
         cmd_array.push(color);
       }
+      if(cpuLevel != null) {
+        cmd_array.push("cpu:" + cpuLevel);
+      }
       let cmd = cmd_array.join(' ');
       this.connect(
         function() {
@@ -161,5 +216,17 @@ var vm = new Vue({
         }
       );
     }, 
+  }, 
+  mounted: function() {
+    // invite links look like reversi.html?room=<room>&code=<code>
+    let params = new URLSearchParams(window.location.search);
+    let room = params.get("room");
+    let code = params.get("code");
+    if (room != null && code != null) {
+      let uname = params.get("name") || window.prompt("Your name", "Guest");
+      if (uname) {
+        this.join(room, uname, code);
+      }
+    }
   }
 });
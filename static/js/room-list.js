@@ -1,5 +1,5 @@
 Vue.component('room-tr', { props: ['room'],
-  template: '<tr><td>{{ room[0] }}</td><td>{{ room[1].player1.name }}</td><td v-if="room[1].player2 != null">{{ room[1].player2.name }}</td><td v-else></td></tr>', 
+  template: '<tr><td>{{ room.name }}<span v-if="room.locked"> (locked)</span></td><td>{{ room.player1 }}</td><td>{{ room.player2 }}</td><td>{{ room.status }}</td><td>{{ room.moves }}</td></tr>',
   computed: {
   }
 })
@@ -12,33 +12,41 @@ let roomListV = new Vue({
           <th scope="col">Room Name</th>
           <th scope="col">Player1</th>
           <th scope="col">Player2</th>
+          <th scope="col">Status</th>
+          <th scope="col">Moves</th>
         </tr>
         <tbody>
-          <room-tr v-for="room in rooms"
-            v-bind:room="room" 
-            :key="room[0]"
+          <room-tr v-for="room in sortedRooms"
+            v-bind:room="room"
+            :key="room.name"
           >
           </room-tr>
         </tbody>
-      </table>`, 
+      </table>`,
   data() {
     return {
-      rooms:[ 
-      ],  
-      conn: null, 
+      // room summaries by name
+      rooms: {},
+      conn: null,
     };
-  }, 
+  },
+  computed: {
+    sortedRooms: function() {
+      let rooms = this.rooms;
+      return Object.keys(rooms).sort().map(function(name) { return rooms[name]; });
+    },
+  },
   methods: {
-    listRooms: function() {
-      this.conn.send("/listRooms");
-    }, 
+    subscribe: function() {
+      this.conn.send(JSON.stringify({type: "SubscribeLobby"}));
+    },
     disconnect: function() {
       if (this.conn != null) {
         console.log('Disconnecting...');
         this.conn.close();
         this.conn = null;
       }
-    }, 
+    },
     connect: function() {
       let that = this;
       that.disconnect();
@@ -48,19 +56,30 @@ let roomListV = new Vue({
       console.log('Connecting...');
       that.conn.onopen = function() {
         console.log('Connected.');
-        that.listRooms();
+        that.rooms = {};
+        that.subscribe();
       };
       that.conn.onmessage = function(e) {
-        that.rooms = JSON.parse(e.data);
-        console.log(JSON.parse(e.data));
-        that.$forceUpdate();
+        let data = JSON.parse(e.data);
+        console.log(data);
+        switch(data.kind) {
+          case "RoomCreated":
+            that.$set(that.rooms, data.body.RoomCreated.name, data.body.RoomCreated);
+            break;
+          case "RoomUpdated":
+            that.$set(that.rooms, data.body.RoomUpdated.name, data.body.RoomUpdated);
+            break;
+          case "RoomClosed":
+            that.$delete(that.rooms, data.body.RoomClosed);
+            break;
+        }
       };
       that.conn.onclose = function() {
         console.log('Disconnected.');
         that.conn = null;
       };
     },
-  }, 
+  },
 })
 
 roomListV.connect();